@@ -1,64 +1,1243 @@
-use std::process::ExitCode;
+use std::fmt::Display;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitCode, Stdio};
+use std::str::FromStr;
 
-use chrono::{Local, NaiveTime, ParseResult};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveTime, ParseResult, Utc, Weekday};
 
-use crate::timelogger::TimeLogger;
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::format::{fmt_dur, parse_date_arg, parse_duration_arg, parse_time_arg};
+use crate::json::JsonObject;
+use crate::timelog::{
+    is_valid_project_name, TimeLogDay, TimeLogEntry, TimeLogEntryType, TimeLogError, TimeLogResult,
+};
+use crate::timelogger::{
+    get_monday_in_week_of, get_sunday_in_week_of, DayDiff, DaySummary, Grouping, PeriodSummary,
+    TimeLogger,
+};
 
-pub fn parse_time_arg(s: &str) -> ParseResult<NaiveTime> {
-    // %R = %H:%M
-    // %H: hour, two digits
-    // %M: minute, two digits
-    NaiveTime::parse_from_str(s, "%R")
-        .or_else(|_| NaiveTime::parse_from_str(s, "%H.%M"))
-        .or_else(|_| NaiveTime::parse_from_str(s, "%H"))
+const ENTRY_DATE_FORMAT: &str = "%Y/%m/%d %a";
+
+// Only used for display, the logfile always has the English weekday names
+#[cfg(feature = "locale")]
+static LOCALE: std::sync::OnceLock<chrono::Locale> = std::sync::OnceLock::new();
+
+pub fn set_locale(locale: &str) -> TimeLogResult<()> {
+    #[cfg(feature = "locale")]
+    {
+        let locale = chrono::Locale::try_from(locale)
+            .map_err(|_| TimeLogError::ParseError(format!("Unknown locale: {}", locale)))?;
+        let _ = LOCALE.set(locale);
+        Ok(())
+    }
+    #[cfg(not(feature = "locale"))]
+    {
+        Err(TimeLogError::ParseError(format!(
+            "Can't use locale {}, timelog was built without the locale feature",
+            locale
+        )))
+    }
+}
+
+#[cfg(feature = "locale")]
+fn format_entry_date(date: NaiveDate, locale: Option<chrono::Locale>) -> String {
+    match locale {
+        Some(locale) => date.format_localized(ENTRY_DATE_FORMAT, locale).to_string(),
+        None => date.format(ENTRY_DATE_FORMAT).to_string(),
+    }
+}
+
+fn fmt_entry_date(date: NaiveDate) -> String {
+    #[cfg(feature = "locale")]
+    return format_entry_date(date, LOCALE.get().copied());
+    #[cfg(not(feature = "locale"))]
+    return date.format(ENTRY_DATE_FORMAT).to_string();
+}
+
+// An entry as it is written to the logfile, but with the weekday in the configured locale
+fn display_entry(entry: &TimeLogEntry) -> String {
+    let canonical = entry.to_string();
+    match canonical.split_once(" | ") {
+        Some((_, rest)) => format!("{} | {}", fmt_entry_date(entry.date()), rest),
+        None => canonical,
+    }
+}
+
+// Filled in proportion to done/total, full when done reaches total
+pub fn progress_bar(done: Duration, total: Duration, width: usize) -> String {
+    let filled = match total.num_seconds() {
+        t if t <= 0 => width,
+        t => ((done.num_seconds().max(0) as usize * width) / t as usize).min(width),
+    };
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+pub fn week_header(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("Week {} {}:", week.week(), week.year())
+}
+
+pub fn get_time(clock: &dyn Clock, s: Option<String>) -> ParseResult<NaiveTime> {
+    match s {
+        Some(x) => parse_time_arg(&x),
+        None => Ok(clock.now().time()),
+    }
+}
+
+pub fn print_projection(surplus: Duration, period: &str) {
+    match surplus < Duration::zero() {
+        true => println!(
+            "At this pace you end the {} {} short",
+            period,
+            fmt_dur(-surplus)
+        ),
+        false => println!(
+            "At this pace you end the {} {} over",
+            period,
+            fmt_dur(surplus)
+        ),
+    }
+}
+
+// Rounds half up to the nearest multiple of `grid`, only for display so the entries keep their times
+pub fn round_dur(dur: Duration, grid: Option<Duration>) -> Duration {
+    match grid.map(|g| g.num_seconds()) {
+        Some(g) if g > 0 => Duration::seconds((dur.num_seconds() + g / 2).div_euclid(g) * g),
+        _ => dur,
+    }
+}
+
+// The time worked line of week and month, rounded to the display grid
+pub fn fmt_worked(
+    summary: &PeriodSummary,
+    round: Option<Duration>,
+    text_fmt: &str,
+    period: &str,
+) -> String {
+    format!(
+        "{} worked {} {}",
+        fmt_dur(round_dur(summary.logged, round)),
+        text_fmt,
+        period
+    )
+}
+
+// The time to use for an entry without an end time today, from either --with or --for
+pub fn get_with_time(
+    tl: &TimeLogger,
+    clock: &dyn Clock,
+    with: Option<String>,
+    for_dur: Option<String>,
+) -> TimeLogResult<NaiveTime> {
+    let now = tl.storage_datetime(clock.today(), clock.now().time());
+    match for_dur {
+        Some(d) => tl.open_entry_end_after(now.date(), parse_duration_arg(&d)?),
+        None => {
+            let time = get_time(clock, with)?;
+            Ok(tl.storage_datetime(clock.today(), time).time())
+        }
+    }
+}
+
+// TODO: There is some duplication between start and end. Fix this.
+pub fn start(
+    tl: &mut TimeLogger,
+    clock: &dyn Clock,
+    time: Option<String>,
+    save: bool,
+    force: bool,
+) -> ExitCode {
+    let time = match get_time(clock, time) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("Unable to update timelog: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let now = tl.log_datetime(clock.now(), time);
+    if let (Some((_, open)), false) = (tl.open_entry_before(now), force) {
+        match tl.logged_time_today(clock) {
+            Ok(worked) => println!(
+                "Already working since {} ({} today), end that first or use --force to start anyway",
+                open,
+                fmt_dur(worked)
+            ),
+            Err(_) => println!(
+                "Already working since {}, end that first or use --force to start anyway",
+                open
+            ),
+        }
+        return ExitCode::FAILURE;
+    }
+
+    let entry = tl.log_start(now.date(), now.time());
+
+    if !save {
+        println!(
+            "Would log: starting {} at {}",
+            entry.ty(),
+            entry.start().expect("The start value was just set")
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(
+        tl,
+        format!("start {} {}", now.date().format("%Y/%m/%d"), now.time()),
+    );
+
+    println!("Logged: starting {} at {}", entry.ty(), entry.start().expect("The start value was just set"));
+
+    ExitCode::SUCCESS
+}
+
+// Counts a started entry as ending at with, like the day summary
+pub fn warn_overtime(tl: &TimeLogger, config: &Config, date: NaiveDate, with: Option<NaiveTime>) {
+    if let (Some(max), Some(tld)) = (config.max_daily_hours, tl.day(date)) {
+        if tld.exceeds(max, with) {
+            eprintln!("WARN: You have worked more than {} today", fmt_dur(max));
+        }
+    }
+}
+
+pub fn end(
+    tl: &mut TimeLogger,
+    clock: &dyn Clock,
+    config: &Config,
+    time: Option<String>,
+    save: bool,
+) -> ExitCode {
+    let time = match get_time(clock, time) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("Unable to update timelog: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let now = tl.log_datetime(clock.now(), time);
+    let past_midnight = match tl.open_entry_before(now) {
+        None => {
+            println!(
+                "Nothing to end, there is no started Work entry on {}",
+                now.date()
+            );
+            return ExitCode::FAILURE;
+        }
+        Some((date, _)) if date != now.date() => true,
+        Some((_, start)) if now.time() < start => {
+            println!(
+                "Can't end the entry started at {} at {}, it is before the start",
+                start, time
+            );
+            return ExitCode::FAILURE;
+        }
+        Some(_) => false,
+    };
+
+    let entry = match past_midnight {
+        true => tl.log_end_past_midnight(now.date(), now.time()),
+        false => tl.log_end(now.date(), now.time()),
+    };
+
+    if !save {
+        println!(
+            "Would log: ending {} at {}",
+            entry.ty(),
+            entry.end().expect("The end value was just set")
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(
+        tl,
+        format!("end {} {}", now.date().format("%Y/%m/%d"), now.time()),
+    );
+
+    println!("Logged: ending {} at {}", entry.ty(), entry.end().expect("The end value was just set"));
+    warn_overtime(tl, config, now.date(), None);
+
+    ExitCode::SUCCESS
+}
+
+pub fn preview_end(tl: &TimeLogger, clock: &dyn Clock, time: Option<String>) -> ExitCode {
+    let time = match get_time(clock, time) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("Unable to preview end: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let now = tl.log_datetime(clock.now(), time);
+    match tl.open_entry_duration(now) {
+        Ok((start, dur)) => println!(
+            "Ending would log {} for the entry started at {}",
+            fmt_dur(dur),
+            start.format("%R")
+        ),
+        Err(e) => {
+            println!("Unable to preview end: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+// The history is only an audit trail, so failing to write it doesn't fail the command
+pub fn record(tl: &TimeLogger, summary: String) {
+    if let Err(e) = tl.record_change(&summary) {
+        eprintln!("WARN: Failed to record the change in the history: {}", e);
+    }
+}
+
+pub fn history(tl: &TimeLogger) -> ExitCode {
+    match tl.history() {
+        Ok(s) => print!("{}", s),
+        Err(e) => {
+            println!("Failed to read the history: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+const DEFAULT_SCREEN_LINES: usize = 24;
+
+fn should_page(is_tty: bool, n_lines: usize, screen_lines: usize) -> bool {
+    is_tty && n_lines > screen_lines
+}
+
+// Collects output and, if it doesn't fit on the terminal, shows it through $PAGER
+pub struct Pager {
+    enabled: bool,
+    buf: String,
+}
+
+impl Pager {
+    pub fn new(enabled: bool) -> Self {
+        Pager {
+            enabled,
+            buf: String::new(),
+        }
+    }
+
+    pub fn line<T: Display>(&mut self, line: T) {
+        self.buf.push_str(&line.to_string());
+        self.buf.push('\n');
+    }
+
+    fn page(&self) -> io::Result<()> {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| String::from("less"));
+        let mut words = pager.split_whitespace();
+        let program = words.next().unwrap_or("less");
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        let write_result = child
+            .stdin
+            .take()
+            .expect("stdin was piped")
+            .write_all(self.buf.as_bytes());
+        child.wait()?;
+        match write_result {
+            // The user quit the pager before reading everything
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            x => x,
+        }
+    }
+
+    pub fn finish(self) {
+        let screen_lines = std::env::var("LINES")
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_SCREEN_LINES);
+        let is_tty = io::stdout().is_terminal();
+        if self.enabled && should_page(is_tty, self.buf.lines().count(), screen_lines) {
+            match self.page() {
+                Ok(()) => return,
+                Err(e) => println!("WARN: Failed to start pager: {}", e),
+            }
+        }
+
+        print!("{}", self.buf);
+    }
+}
+
+pub fn view_count(n_entries: Option<usize>, config: &Config) -> usize {
+    n_entries.unwrap_or(config.view_default_count)
+}
+
+pub struct ViewOptions {
+    pub n_entries: Option<usize>,
+    pub ty: Option<TimeLogEntryType>,
+    pub index: bool,
+    pub pager: bool,
+    pub only_incomplete: bool,
+}
+
+fn view_days<'a>(tl: &'a TimeLogger, config: &Config, opts: &ViewOptions) -> Vec<&'a TimeLogDay> {
+    let n = view_count(opts.n_entries, config);
+    if !opts.only_incomplete {
+        return tl.get_latest_n_entries(n);
+    }
+
+    // The latest n incomplete days, not the incomplete ones among the latest n
+    let mut days = tl.days();
+    days.retain(|tld| tld.has_unfinished_entries());
+    days.split_off(days.len().saturating_sub(n))
+}
+
+pub fn view(tl: &TimeLogger, config: &Config, opts: &ViewOptions) -> ExitCode {
+    let mut pager = Pager::new(opts.pager);
+    for tld in view_days(tl, config, opts) {
+        for (i, entry) in tld.entries().iter().enumerate() {
+            if opts.ty.is_some_and(|ty| ty != entry.ty()) {
+                continue;
+            }
+
+            match opts.index {
+                true => pager.line(format!("[{}] {}", i, display_entry(entry))),
+                false => pager.line(display_entry(entry)),
+            }
+        }
+    }
+    pager.finish();
+
+    ExitCode::SUCCESS
+}
+
+pub fn recent(tl: &TimeLogger, config: &Config, n_entries: Option<usize>) -> ExitCode {
+    for entry in tl.recent_entries(view_count(n_entries, config)) {
+        println!("{}", display_entry(entry));
+    }
+
+    ExitCode::SUCCESS
+}
+
+// From --after and/or --before, the window is open ended on the side that isn't given
+pub fn time_window_arg(
+    after: Option<&str>,
+    before: Option<&str>,
+) -> TimeLogResult<Option<(NaiveTime, NaiveTime)>> {
+    if after.is_none() && before.is_none() {
+        return Ok(None);
+    }
+
+    let start = after
+        .map(parse_time_arg)
+        .transpose()?
+        .unwrap_or(NaiveTime::from_hms(0, 0, 0));
+    let end = match before {
+        Some(x) => parse_time_arg(x)?,
+        None => NaiveTime::from_hms_nano(23, 59, 59, 999_999_999),
+    };
+    if start > end {
+        return Err(TimeLogError::inv_inp("'after' is after 'before'"));
+    }
+
+    Ok(Some((start, end)))
+}
+
+pub fn summary(
+    tl: &TimeLogger,
+    by_project: bool,
+    window: Option<(NaiveTime, NaiveTime)>,
+    round: Option<Duration>,
+) -> ExitCode {
+    let today = Local::today().naive_local();
+    let from = get_monday_in_week_of(today);
+    let to = get_sunday_in_week_of(today);
+
+    println!("Summary for {} - {}", from, to);
+    if by_project {
+        let totals = match window {
+            None => tl.totals_by_project(from, to),
+            Some(w) => tl.totals_within(from, to, w, |e| {
+                (e.ty() == TimeLogEntryType::Work).then(|| e.project().map(String::from))
+            }),
+        };
+        for (project, dur) in totals {
+            println!(
+                "{}: {}",
+                project.as_deref().unwrap_or("(no project)"),
+                fmt_dur(round_dur(dur, round))
+            );
+        }
+    } else {
+        let totals = match window {
+            None => tl.totals_by_type(from, to),
+            Some(w) => tl.totals_within(from, to, w, |e| Some(e.ty())),
+        };
+        for (ty, dur) in totals {
+            println!("{}: {}", ty, fmt_dur(round_dur(dur, round)));
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+// The range of the whole log when --from/--to are not given, None if the log is empty
+fn date_range_arg(
+    tl: &TimeLogger,
+    from: &str,
+    to: &str,
+) -> TimeLogResult<Option<(NaiveDate, NaiveDate)>> {
+    if from.is_empty() {
+        return Ok(tl.date_range());
+    }
+
+    let (from, to) = (parse_date_arg(from)?, parse_date_arg(to)?);
+    if from > to {
+        return Err(TimeLogError::inv_inp("'from' is after 'to'"));
+    }
+
+    Ok(Some((from, to)))
+}
+
+pub fn stats(tl: &TimeLogger, config: &Config, from: &str, to: &str, flex_trend: bool) -> ExitCode {
+    let (from, to) = match date_range_arg(tl, from, to) {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("The logfile is empty");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            println!("Invalid --from/--to: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let stats = tl.stats(from, to);
+    println!("Stats for {} - {}", from, to);
+    println!("Days worked: {}", stats.n_days);
+    println!("Average day: {}", fmt_dur(stats.average()));
+    if let Some((date, dur)) = tl.busiest_day(from, to) {
+        println!("Busiest day: {} ({})", fmt_dur(dur), date);
+    }
+    if let Some((date, dur)) = tl.quietest_day(from, to) {
+        println!("Quietest day: {} ({})", fmt_dur(dur), date);
+    }
+    println!("Avg by weekday:");
+    let mut weekday = Weekday::Mon;
+    for avg in stats.avg_by_weekday {
+        if avg > Duration::zero() {
+            println!("  {}: {}", weekday, fmt_dur(avg));
+        }
+        weekday = weekday.succ();
+    }
+    if flex_trend && !config.flex_enabled {
+        println!("Flex is disabled in the config");
+    } else if flex_trend {
+        println!("Flex by week:");
+        let last = get_sunday_in_week_of(to);
+        for (sunday, flex) in tl.weekly_flex_series() {
+            if (from..=last).contains(&sunday) {
+                println!("  {}: {}", sunday.format("%Y/%m/%d"), fmt_dur(flex));
+            }
+        }
+    }
+    println!(
+        "Logfile: {} days, {} entries",
+        tl.days_count(),
+        tl.entries_count()
+    );
+
+    ExitCode::SUCCESS
 }
 
-pub fn get_time(s: Option<String>) -> ParseResult<NaiveTime> {
-    match s {
-        Some(x) => parse_time_arg(&x),
-        None => Ok(Local::now().time()),
+const ICS_DATETIME_FMT: &str = "%Y%m%dT%H%M%S";
+
+// Escaped as a TEXT value in RFC 5545
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+}
+
+// One VEVENT per completed Work interval, open intervals are skipped
+fn write_ics(days: &[&TimeLogDay], utc: bool) -> String {
+    // Times are floating (local) unless the logfile is stored in UTC
+    let suffix = if utc { "Z" } else { "" };
+    // Always in UTC, it is when the calendar was created rather than a time of the event
+    let stamp = format!("{}Z", Utc::now().format(ICS_DATETIME_FMT));
+    let mut s = String::new();
+    s.push_str("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//timelog//timelog//EN\r\n");
+    for tld in days {
+        for e in tld.filtered_by_type(TimeLogEntryType::Work) {
+            let (start, end) = match (e.start(), e.duration()) {
+                (Some(start), Some(dur)) => {
+                    (e.date().and_time(start), e.date().and_time(start) + dur)
+                }
+                _ => continue,
+            };
+            let start = format!("{}{}", start.format(ICS_DATETIME_FMT), suffix);
+            let end = format!("{}{}", end.format(ICS_DATETIME_FMT), suffix);
+            let summary = match e.project() {
+                Some(p) => format!("{}@{}", e.ty(), p),
+                None => e.ty().to_string(),
+            };
+            s.push_str("BEGIN:VEVENT\r\n");
+            s.push_str(&format!("UID:{}-timelog\r\n", start));
+            s.push_str(&format!("DTSTAMP:{}\r\n", stamp));
+            s.push_str(&format!("DTSTART:{}\r\n", start));
+            s.push_str(&format!("DTEND:{}\r\n", end));
+            s.push_str(&format!("SUMMARY:{}\r\n", ics_text(&summary)));
+            s.push_str("END:VEVENT\r\n");
+        }
+    }
+    s.push_str("END:VCALENDAR\r\n");
+
+    s
+}
+
+// Quoted as in RFC 4180 when the field contains the delimiter, a quote or a line break
+fn csv_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv(days: &[&TimeLogDay], delimiter: char) -> String {
+    let mut s = String::new();
+    let mut push_row = |fields: &[String]| {
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f, delimiter)).collect();
+        s.push_str(&row.join(&delimiter.to_string()));
+        s.push_str("\r\n");
+    };
+
+    push_row(&["date", "type", "project", "start", "end", "hours"].map(String::from));
+    for e in days.iter().flat_map(|tld| tld.entries()) {
+        let fmt_time = |t: Option<NaiveTime>| t.map(|t| t.format("%R").to_string());
+        push_row(&[
+            e.date().format("%Y/%m/%d").to_string(),
+            e.ty().to_string(),
+            e.project().unwrap_or_default().to_string(),
+            fmt_time(e.start()).unwrap_or_default(),
+            fmt_time(e.end()).unwrap_or_default(),
+            e.duration().map(fmt_dur).unwrap_or_default(),
+        ]);
+    }
+
+    s
+}
+
+// Only entries with both a start and an end get a duration, and count towards the total
+fn write_markdown(days: &[&TimeLogDay]) -> String {
+    let mut s = String::from("| Date | Type | Start | End | Hours |\n|---|---|---|---|---|\n");
+    let mut total = Duration::zero();
+    for e in days.iter().flat_map(|tld| tld.entries()) {
+        let ty = match e.project() {
+            Some(p) => format!("{}@{}", e.ty(), p),
+            None => e.ty().to_string(),
+        };
+        let fmt_time = |t: Option<NaiveTime>| t.map(|t| t.format("%R").to_string());
+        let hours = match e.duration() {
+            Some(dur) => {
+                total = total + dur;
+                fmt_dur(dur)
+            }
+            None => String::new(),
+        };
+        s.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            e.date().format("%Y/%m/%d"),
+            ty,
+            fmt_time(e.start()).unwrap_or_default(),
+            fmt_time(e.end()).unwrap_or_default(),
+            hours
+        ));
+    }
+    s.push_str(&format!("| **Total** | | | | {} |\n", fmt_dur(total)));
+
+    s
+}
+
+fn write_markdown_grouped(groups: &[(NaiveDate, NaiveDate, Duration)]) -> String {
+    let mut s = String::from("| From | To | Hours |\n|---|---|---|\n");
+    for (from, to, dur) in groups {
+        s.push_str(&format!(
+            "| {} | {} | {} |\n",
+            from.format("%Y/%m/%d"),
+            to.format("%Y/%m/%d"),
+            fmt_dur(*dur)
+        ));
+    }
+
+    s
+}
+
+fn write_csv_grouped(groups: &[(NaiveDate, NaiveDate, Duration)], delimiter: char) -> String {
+    let mut s = String::new();
+    let mut push_row = |fields: &[String]| {
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f, delimiter)).collect();
+        s.push_str(&row.join(&delimiter.to_string()));
+        s.push_str("\r\n");
+    };
+
+    push_row(&["from", "to", "hours"].map(String::from));
+    for (from, to, dur) in groups {
+        push_row(&[
+            from.format("%Y/%m/%d").to_string(),
+            to.format("%Y/%m/%d").to_string(),
+            fmt_dur(*dur),
+        ]);
+    }
+
+    s
+}
+
+// An array with one object per group
+fn write_json_grouped(groups: &[(NaiveDate, NaiveDate, Duration)]) -> String {
+    let objects: Vec<String> = groups
+        .iter()
+        .map(|(from, to, dur)| {
+            JsonObject::new()
+                .str("from", &from.format("%Y/%m/%d").to_string())
+                .str("to", &to.format("%Y/%m/%d").to_string())
+                .int("total_min", dur.num_minutes())
+                .to_string()
+        })
+        .collect();
+
+    format!("[{}]", objects.join(","))
+}
+
+fn csv_delimiter_arg(csv_delimiter: Option<&str>) -> TimeLogResult<char> {
+    match csv_delimiter.map(|d| (d.chars().next(), d.chars().count())) {
+        None => Ok(','),
+        Some((Some(c), 1)) if !matches!(c, '"' | '\r' | '\n') => Ok(c),
+        Some(_) => Err(TimeLogError::inv_inp(
+            "The CSV delimiter must be a single character other than a quote or newline",
+        )),
+    }
+}
+
+pub fn export_grouped(
+    tl: &TimeLogger,
+    format: &str,
+    from: &str,
+    to: &str,
+    group_by: &str,
+    csv_delimiter: Option<&str>,
+) -> ExitCode {
+    let delimiter = match csv_delimiter_arg(csv_delimiter) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Invalid --csv-delimiter: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let grouping: Grouping = match group_by.parse() {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to parse --group-by: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (from, to) = match date_range_arg(tl, from, to) {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("The logfile is empty");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            println!("Invalid --from/--to: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let groups = tl.grouped_totals(from, to, grouping);
+    match format {
+        "md" => print!("{}", write_markdown_grouped(&groups)),
+        "csv" => print!("{}", write_csv_grouped(&groups, delimiter)),
+        "json" => println!("{}", write_json_grouped(&groups)),
+        _ => {
+            println!("Unsupported format for grouped export: {}", format);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+pub fn export(
+    tl: &TimeLogger,
+    format: &str,
+    from: &str,
+    to: &str,
+    csv_delimiter: Option<&str>,
+) -> ExitCode {
+    let delimiter = match csv_delimiter_arg(csv_delimiter) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Invalid --csv-delimiter: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let days = match date_range_arg(tl, from, to) {
+        Ok(Some((from, to))) => tl
+            .days()
+            .into_iter()
+            .filter(|tld| (from..=to).contains(&tld.date()))
+            .collect(),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            println!("Invalid --from/--to: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match format {
+        "ics" => print!("{}", write_ics(&days, tl.is_utc())),
+        "md" => print!("{}", write_markdown(&days)),
+        "csv" => print!("{}", write_csv(&days, delimiter)),
+        _ => {
+            println!("Unsupported export format: {}", format);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+// Points at the incomplete entries of each day, days that are only invalid are listed by date
+pub fn print_incomplete(tl: &TimeLogger, dates: &[NaiveDate]) {
+    for (date, tld) in dates.iter().filter_map(|d| tl.day(*d).map(|tld| (d, tld))) {
+        let open = tld.open_entries();
+        if open.is_empty() {
+            println!("Invalid entries: {}", date);
+        }
+        for i in open {
+            println!("Incomplete entry: {}", tld.fmt_indexed(i));
+        }
+    }
+}
+
+fn print_prefixed(prefix: &str, tld: &TimeLogDay) {
+    for line in tld.to_string().lines() {
+        println!("{} {}", prefix, line);
+    }
+}
+
+pub fn diff(tl: &TimeLogger, other: &str) -> ExitCode {
+    let path = PathBuf::from(other);
+    if !path.exists() {
+        println!("No such file: {}", other);
+        return ExitCode::FAILURE;
+    }
+
+    let other = match TimeLogger::from_file(path) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to read {}: {}", other, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for d in tl.diff(&other) {
+        match d {
+            DayDiff::OnlyInSelf(tld) => print_prefixed("-", tld),
+            DayDiff::OnlyInOther(tld) => print_prefixed("+", tld),
+            DayDiff::Changed(ours, theirs) => {
+                print_prefixed("-", ours);
+                print_prefixed("+", theirs);
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+pub fn delete(tl: &mut TimeLogger, date: &str, index: usize) -> ExitCode {
+    let date = match parse_date_arg(date) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to parse NaiveDate for <date>: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let entry = match tl.delete_entry(date, index) {
+        Some(x) => x,
+        None => {
+            println!("No entry [{}] on {}", index, date.format("%Y/%m/%d"));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(
+        tl,
+        format!("delete {} [{}]", date.format("%Y/%m/%d"), index),
+    );
+
+    println!("Deleted: {}", entry);
+
+    ExitCode::SUCCESS
+}
+
+pub fn delete_type(tl: &mut TimeLogger, date: &str, ty: &str) -> ExitCode {
+    let date = match parse_date_arg(date) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to parse NaiveDate for <date>: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let ty = match TimeLogEntryType::from_str(ty) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to parse TimeLogEntryType for --type: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let count = tl.delete_entries_where(date, |e| e.ty() == ty);
+    if count == 0 {
+        println!("No {} entries on {}", ty, date.format("%Y/%m/%d"));
+        return ExitCode::SUCCESS;
+    }
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(
+        tl,
+        format!("delete {} --type {}", date.format("%Y/%m/%d"), ty),
+    );
+
+    println!(
+        "Deleted {} {} entries on {}",
+        count,
+        ty,
+        date.format("%Y/%m/%d")
+    );
+
+    ExitCode::SUCCESS
+}
+
+pub fn rename_project(tl: &mut TimeLogger, old: &str, new: &str) -> ExitCode {
+    if !is_valid_project_name(new) {
+        println!("Invalid project name: {}", new);
+        return ExitCode::FAILURE;
+    }
+
+    let count = tl.rename_project(old, new);
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(tl, format!("project rename {} {}", old, new));
+
+    println!("Renamed {} to {} in {} entries", old, new, count);
+
+    ExitCode::SUCCESS
+}
+
+// The lines that differ between `old` and `new`, prefixed with "-" or "+", in file order
+fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut out = Vec::new();
+    diff_into(old, new, &mut out);
+
+    out
+}
+
+// Lengths of the longest common subsequences of `old` and each prefix of `new`
+fn lcs_lengths(old: &[&str], new: &[&str]) -> Vec<usize> {
+    let mut row = vec![0; new.len() + 1];
+    for line in old {
+        let mut diag = 0;
+        for j in 1..=new.len() {
+            let above = row[j];
+            row[j] = match *line == new[j - 1] {
+                true => diag + 1,
+                false => row[j].max(row[j - 1]),
+            };
+            diag = above;
+        }
+    }
+
+    row
+}
+
+// Hirschberg's algorithm: split old in half and new where the halves share the most lines,
+// which finds a minimal diff in linear rather than quadratic space
+fn diff_into(old: &[&str], new: &[&str], out: &mut Vec<String>) {
+    if old.len() <= 1 {
+        let same = old.first().and_then(|l| new.iter().position(|n| n == l));
+        if same.is_none() {
+            out.extend(old.iter().map(|l| format!("- {}", l)));
+        }
+        for (j, l) in new.iter().enumerate() {
+            if Some(j) != same {
+                out.push(format!("+ {}", l));
+            }
+        }
+        return;
+    }
+
+    let mid = old.len() / 2;
+    let fwd = lcs_lengths(&old[..mid], new);
+    let rev_old: Vec<&str> = old[mid..].iter().rev().copied().collect();
+    let rev_new: Vec<&str> = new.iter().rev().copied().collect();
+    let bwd = lcs_lengths(&rev_old, &rev_new);
+    // The first best split, so that removed lines come before added ones
+    let split = (0..=new.len())
+        .rev()
+        .max_by_key(|&j| fwd[j] + bwd[new.len() - j])
+        .expect("There is always at least one split");
+
+    diff_into(&old[..mid], &new[..split], out);
+    diff_into(&old[mid..], &new[split..], out);
+}
+
+// Prints how saving would change the logfile on disk
+fn print_dry_run(tl: &TimeLogger) -> ExitCode {
+    let on_disk = match tl.read_on_disk() {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Failed to read the logfile: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let changes = line_diff(&on_disk, &tl.write_entries());
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    for line in changes {
+        println!("{}", line);
+    }
+
+    ExitCode::SUCCESS
+}
+
+pub fn compact(tl: &mut TimeLogger, dry_run: bool) -> ExitCode {
+    let removed = tl.compact();
+    if dry_run {
+        return print_dry_run(tl);
+    }
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(tl, String::from("compact"));
+
+    println!("Compacted the logfile, removed {} entries", removed);
+
+    ExitCode::SUCCESS
+}
+
+fn confirm(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
+
+// Without `yes`, asks for confirmation when run interactively and aborts otherwise
+pub fn clear(tl: &mut TimeLogger, yes: bool) -> ExitCode {
+    if !yes && !confirm("Remove all entries from the logfile?") {
+        println!("Aborted, pass --yes to clear without confirmation");
+        return ExitCode::FAILURE;
+    }
+
+    tl.clear();
+
+    if let Err(e) = tl.save_allow_empty() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
     }
+    record(tl, String::from("clear"));
+
+    println!("Cleared all entries from the logfile");
+
+    ExitCode::SUCCESS
 }
 
-// TODO: There is some duplication between start and end. Fix this.
-pub fn start(tl: &mut TimeLogger, time: Option<String>) -> ExitCode {
-    let time = match get_time(time) {
-        Ok(t) => t,
+pub fn undo(tl: &TimeLogger) -> ExitCode {
+    match tl.undo() {
+        Ok((bkp, undone)) => {
+            record(tl, format!("undo, restored {}", bkp.display()));
+            println!("Restored the logfile from {}", bkp.display());
+            println!("The replaced logfile was kept as {}", undone.display());
+        }
         Err(e) => {
-            println!("Unable to update timelog: {}", e);
+            println!("Failed to undo: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+// Only json is supported besides the default text output
+pub fn json_format_arg(format: Option<&str>) -> TimeLogResult<bool> {
+    match format {
+        None => Ok(false),
+        Some("json") => Ok(true),
+        Some(f) => Err(TimeLogError::inv_inp(
+            format!("Unsupported output format: {}", f).as_str(),
+        )),
+    }
+}
+
+// The time worked today, and for how long the open entry has been going on at now
+pub fn day_summary_json(summary: &DaySummary, now: NaiveTime) -> JsonObject {
+    let json = JsonObject::new().bool("clocked_in", summary.open_since.is_some());
+    let json = match summary.open_since {
+        Some(since) => json.str("since", &since.format("%R").to_string()).int(
+            "elapsed_min",
+            now.signed_duration_since(since).num_minutes(),
+        ),
+        None => json,
+    };
+    json.int("worked_min", summary.worked.num_minutes())
+        .int("left_min", summary.remaining.num_minutes())
+}
+
+pub fn past_day_json(date: NaiveDate, worked: Duration) -> JsonObject {
+    JsonObject::new()
+        .str("date", &date.format("%Y/%m/%d").to_string())
+        .int("worked_min", worked.num_minutes())
+}
+
+// The worked time is rounded like in the text output, and the projection is only included
+// when asked for
+pub fn period_summary_json(
+    summary: &PeriodSummary,
+    round: Option<Duration>,
+    projection: Option<Duration>,
+) -> JsonObject {
+    let json = JsonObject::new()
+        .int("worked_min", round_dur(summary.logged, round).num_minutes())
+        .int("loggable_min", summary.loggable.num_minutes())
+        .int("flex_min", summary.flex.num_minutes())
+        .int("left_min", summary.remaining.num_minutes());
+    match projection {
+        Some(p) => json.int("projected_min", p.num_minutes()),
+        None => json,
+    }
+}
+
+fn flex_json(date: NaiveDate, flex: Duration) -> JsonObject {
+    JsonObject::new()
+        .str("date", &date.format("%Y/%m/%d").to_string())
+        .int("flex_min", flex.num_minutes())
+}
+
+pub fn flex(tl: &TimeLogger, clock: &dyn Clock, format: &str) -> ExitCode {
+    let today = clock.today();
+    let flex = tl.flextime_as_of(today);
+    match format {
+        "" => println!("{} flex as of today", fmt_dur(flex)),
+        "json" => println!("{}", flex_json(today, flex)),
+        _ => {
+            println!("Unsupported output format: {}", format);
             return ExitCode::FAILURE;
         }
+    }
+
+    ExitCode::SUCCESS
+}
+
+pub fn reset_flex(tl: &mut TimeLogger, date: Option<String>) -> ExitCode {
+    let date = match date {
+        Some(x) => match parse_date_arg(&x) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("Failed to parse NaiveDate for <date>: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Local::today().naive_local(),
     };
 
-    let entry = tl.log_start(Local::today().naive_local(), time);
+    tl.reset_flex(date);
 
     if let Err(e) = tl.save() {
         println!("Failed to save to logfile: {}", e);
         return ExitCode::FAILURE;
     }
+    record(tl, format!("flex reset {}", date.format("%Y/%m/%d")));
 
-    println!("Logged: starting {} at {}", entry.ty(), entry.start().expect("The start value was just set"));
+    println!("Flex reset on {}", date.format("%Y/%m/%d"));
 
     ExitCode::SUCCESS
 }
 
-pub fn end(tl: &mut TimeLogger, time: Option<String>) -> ExitCode {
-    let time = match get_time(time) {
-        Ok(t) => t,
-        Err(e) => {
-            println!("Unable to update timelog: {}", e);
-            return ExitCode::FAILURE;
-        }
-    };
+pub fn normalize(tl: &mut TimeLogger, dry_run: bool) -> ExitCode {
+    if dry_run {
+        return print_dry_run(tl);
+    }
+
+    if let Err(e) = tl.save() {
+        println!("Failed to save to logfile: {}", e);
+        return ExitCode::FAILURE;
+    }
+    record(tl, String::from("normalize"));
+
+    println!("Rewrote the logfile in canonical form");
+
+    ExitCode::SUCCESS
+}
 
-    let entry = tl.log_end(Local::today().naive_local(), time);
+pub fn migrate_to_utc(tl: &mut TimeLogger) -> ExitCode {
+    if let Err(e) = tl.migrate_to_utc(*Local::now().offset()) {
+        println!("Failed to migrate to UTC: {}", e);
+        return ExitCode::FAILURE;
+    }
 
     if let Err(e) = tl.save() {
         println!("Failed to save to logfile: {}", e);
         return ExitCode::FAILURE;
     }
+    record(tl, String::from("migrate --to-utc"));
 
-    println!("Logged: ending {} at {}", entry.ty(), entry.end().expect("The end value was just set"));
+    println!("Migrated the logfile to UTC");
 
     ExitCode::SUCCESS
 }
@@ -66,32 +1245,605 @@ pub fn end(tl: &mut TimeLogger, time: Option<String>) -> ExitCode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::{FixedClock, SystemClock};
     use chrono::NaiveTime;
+
+    #[test]
+    fn flex_as_json() {
+        let date = NaiveDate::from_ymd(2018, 1, 1);
+        assert_eq!(
+            flex_json(date, -Duration::minutes(90)).to_string(),
+            r#"{"date":"2018/01/01","flex_min":-90}"#
+        );
+    }
+
+    #[test]
+    fn round_period_total() {
+        let s = "2017/12/18 Mon | Work 08:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 17:00:00\n\
+                 2017/12/20 Wed | Work 08:00:00 17:00:00\n\
+                 2017/12/21 Thu | Work 08:00:00 16:52:00\n\
+                 2017/12/22 Fri | Work 08:00:00 10:00:00\n";
+        let tl: TimeLogger = s.parse().unwrap();
+        let logged = tl
+            .week_summary_with(NaiveDate::from_ymd(2017, 12, 18), None)
+            .unwrap()
+            .logged;
+        assert_eq!(logged, Duration::minutes(37 * 60 + 52));
+
+        let hour = Some(Duration::hours(1));
+        assert_eq!(round_dur(logged, hour), Duration::hours(38));
+        assert_eq!(round_dur(logged, None), logged);
+        assert_eq!(
+            round_dur(Duration::minutes(37 * 60 + 29), hour),
+            Duration::hours(37)
+        );
+        assert_eq!(
+            round_dur(logged, Some(Duration::minutes(15))),
+            Duration::minutes(37 * 60 + 45)
+        );
+        let summary = tl
+            .week_summary_with(NaiveDate::from_ymd(2017, 12, 18), None)
+            .unwrap();
+        assert_eq!(
+            fmt_worked(&summary, hour, "this", "week"),
+            format!("{} worked this week", fmt_dur(Duration::hours(38)))
+        );
+        assert_eq!(
+            tl.days()[3].to_string(),
+            "2017/12/21 Thu | Work 08:00:00 16:52:00"
+        );
+    }
+
+    #[test]
+    fn summaries_as_json() {
+        assert_eq!(json_format_arg(None), Ok(false));
+        assert_eq!(json_format_arg(Some("json")), Ok(true));
+        assert!(json_format_arg(Some("xml")).is_err());
+
+        let open = DaySummary {
+            worked: Duration::minutes(161),
+            loggable: Duration::hours(8),
+            remaining: Duration::minutes(8 * 60 - 161),
+            open_since: Some(NaiveTime::from_hms(8, 13, 0)),
+        };
+        assert_eq!(
+            day_summary_json(&open, NaiveTime::from_hms(10, 54, 0)).to_string(),
+            r#"{"clocked_in":true,"since":"08:13","elapsed_min":161,"worked_min":161,"left_min":319}"#
+        );
+        let closed = DaySummary {
+            open_since: None,
+            ..open
+        };
+        assert_eq!(
+            day_summary_json(&closed, NaiveTime::from_hms(10, 54, 0)).to_string(),
+            r#"{"clocked_in":false,"worked_min":161,"left_min":319}"#
+        );
+
+        let week = PeriodSummary {
+            logged: Duration::hours(30),
+            loggable: Duration::hours(40),
+            flex: Duration::minutes(-30),
+            remaining: Duration::minutes(10 * 60 + 30),
+        };
+        assert_eq!(
+            period_summary_json(&week, None, None).to_string(),
+            r#"{"worked_min":1800,"loggable_min":2400,"flex_min":-30,"left_min":630}"#
+        );
+        let rounded = PeriodSummary {
+            logged: Duration::minutes(30 * 60 + 40),
+            ..week
+        };
+        assert_eq!(
+            period_summary_json(
+                &rounded,
+                Some(Duration::hours(1)),
+                Some(Duration::hours(-2))
+            )
+            .to_string(),
+            r#"{"worked_min":1860,"loggable_min":2400,"flex_min":-30,"left_min":630,"projected_min":-120}"#
+        );
+        assert_eq!(
+            past_day_json(NaiveDate::from_ymd(2018, 1, 1), Duration::hours(8)).to_string(),
+            r#"{"date":"2018/01/01","worked_min":480}"#
+        );
+    }
+
+    #[test]
+    fn time_window_from_args() {
+        let hm = |h, m| NaiveTime::from_hms(h, m, 0);
+        assert_eq!(time_window_arg(None, None), Ok(None));
+        assert_eq!(
+            time_window_arg(Some("17:00"), Some("20:30")),
+            Ok(Some((hm(17, 0), hm(20, 30))))
+        );
+        assert_eq!(
+            time_window_arg(None, Some("08:00")),
+            Ok(Some((hm(0, 0), hm(8, 0))))
+        );
+        assert!(time_window_arg(Some("5pm"), None).is_err());
+        assert!(time_window_arg(Some("20:30"), Some("17:00")).is_err());
+    }
+
+    #[test]
+    fn progress_bar_rendering() {
+        assert_eq!(
+            progress_bar(Duration::hours(32), Duration::hours(40), 10),
+            "[########--]"
+        );
+        assert_eq!(
+            progress_bar(Duration::zero(), Duration::hours(40), 4),
+            "[----]"
+        );
+        assert_eq!(
+            progress_bar(Duration::hours(45), Duration::hours(40), 4),
+            "[####]"
+        );
+    }
+
+    #[test]
+    fn week_header_iso_week() {
+        assert_eq!(
+            week_header(NaiveDate::from_ymd(2017, 12, 20)),
+            "Week 51 2017:"
+        );
+        // Belongs to the first ISO week of the next year
+        assert_eq!(
+            week_header(NaiveDate::from_ymd(2024, 12, 30)),
+            "Week 1 2025:"
+        );
+    }
+
+    #[test]
+    fn pager_only_for_long_tty_output() {
+        assert!(!should_page(false, 1000, 24));
+        assert!(!should_page(true, 24, 24));
+        assert!(should_page(true, 25, 24));
+
+        let mut pager = Pager::new(true);
+        pager.line("first");
+        pager.line(3);
+        assert_eq!(pager.buf, "first\n3\n");
+    }
+
+    #[test]
+    fn start_with_fixed_clock() {
+        let now = NaiveDate::from_ymd(2018, 1, 1).and_hms(8, 13, 47);
+        let mut tl: TimeLogger = "".parse().unwrap();
+
+        assert_eq!(
+            start(&mut tl, &FixedClock(now), None, false, false),
+            ExitCode::SUCCESS
+        );
+
+        let days = tl.days();
+        assert_eq!(days.len(), 1);
+        let entry = &days[0].entries()[0];
+        assert_eq!(entry.date(), now.date());
+        // Seconds are dropped unless second precision is configured
+        assert_eq!(entry.start(), Some(NaiveTime::from_hms(8, 13, 0)));
+    }
+
+    #[test]
+    fn display_entry_keeps_canonical_form() {
+        let line = "2018/01/01 Mon | Work@acme 08:00:00 16:00:00";
+        let entry: TimeLogEntry = line.parse().unwrap();
+        assert_eq!(display_entry(&entry), line);
+    }
+
+    #[cfg(feature = "locale")]
+    #[test]
+    fn format_entry_date_localized() {
+        let date = NaiveDate::from_ymd(2018, 1, 1);
+        assert_eq!(format_entry_date(date, None), "2018/01/01 Mon");
+        assert_eq!(
+            format_entry_date(date, Some(chrono::Locale::sv_SE)),
+            "2018/01/01 mån"
+        );
+        assert_eq!(
+            format_entry_date(date, Some(chrono::Locale::de_DE)),
+            "2018/01/01 Mo"
+        );
+    }
+
+    #[test]
+    fn compact_dry_run() {
+        let path =
+            std::env::temp_dir().join(format!("timelog_compact_dry_run_{}", std::process::id()));
+        let contents = "2018/01/01 Mon | Work 08:00:00 12:00:00\n\
+                        2018/01/01 Mon | Work 12:00:00 16:00:00\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let mut tl = TimeLogger::from_file(path.clone()).unwrap();
+        assert_eq!(compact(&mut tl, true), ExitCode::SUCCESS);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+
+        assert_eq!(
+            line_diff(contents, &tl.write_entries()),
+            vec![
+                "- 2018/01/01 Mon | Work 08:00:00 12:00:00",
+                "- 2018/01/01 Mon | Work 12:00:00 16:00:00",
+                "+ 2018/01/01 Mon | Work 08:00:00 16:00:00",
+            ]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn normalize_dry_run() {
+        let path =
+            std::env::temp_dir().join(format!("timelog_normalize_dry_run_{}", std::process::id()));
+        let contents = "2018/01/02 Tue | Work 08:00:00 12:00:00\n\
+                        2018/01/01 Mon | Work 08:00:00 12:00:00\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let mut tl = TimeLogger::from_file(path.clone()).unwrap();
+        assert_eq!(normalize(&mut tl, true), ExitCode::SUCCESS);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        assert_eq!(
+            line_diff(contents, &tl.write_entries()),
+            vec![
+                "- 2018/01/02 Tue | Work 08:00:00 12:00:00",
+                "+ 2018/01/02 Tue | Work 08:00:00 12:00:00",
+            ]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn line_diff_keeps_order() {
+        assert!(line_diff("a\nb\n", "a\nb\n").is_empty());
+        assert_eq!(
+            line_diff("a\nb\nc\nd\n", "a\nx\nc\nd\ny\n"),
+            vec!["- b", "+ x", "+ y"]
+        );
+        assert_eq!(
+            line_diff("a\nb\nc\nd\ne\n", "b\nx\nd\ne\nf\n"),
+            vec!["- a", "- c", "+ x", "+ f"]
+        );
+        assert_eq!(line_diff("", "a\n"), vec!["+ a"]);
+        assert_eq!(line_diff("a\n", ""), vec!["- a"]);
+    }
+
+    #[test]
+    fn start_end_no_save() {
+        let path =
+            std::env::temp_dir().join(format!("timelog_start_end_no_save_{}", std::process::id()));
+        let contents = "2018/01/01 Mon | Work 08:00:00 16:00:00\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let mut tl = TimeLogger::from_file(path.clone()).unwrap();
+        assert_eq!(
+            start(
+                &mut tl,
+                &SystemClock,
+                Some(String::from("08:00")),
+                false,
+                false
+            ),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            end(
+                &mut tl,
+                &SystemClock,
+                &Config::default(),
+                Some(String::from("16:00")),
+                false
+            ),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn start_end_past_day_boundary() {
+        let mut tl: TimeLogger = "2018/01/01 Mon | Work 08:00:00 12:00:00".parse().unwrap();
+        tl.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        let jan2 = NaiveDate::from_ymd(2018, 1, 2);
+
+        let clock = FixedClock(jan2.and_hms(0, 45, 0));
+        assert_eq!(
+            start(&mut tl, &clock, None, false, false),
+            ExitCode::SUCCESS
+        );
+        let clock = FixedClock(jan2.and_hms(1, 30, 0));
+        assert_eq!(
+            end(&mut tl, &clock, &Config::default(), None, false),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            tl.day(jan2).unwrap().to_string(),
+            "2018/01/02 Tue | Work 00:45:00 01:30:00"
+        );
+        assert_eq!(
+            tl.day(jan2.pred()).unwrap().to_string(),
+            "2018/01/01 Mon | Work 08:00:00 12:00:00"
+        );
+
+        // Started the evening before and ended after midnight, on the same logical day
+        let mut tl: TimeLogger = "2018/01/01 Mon | Work 22:00:00 UNDEF".parse().unwrap();
+        tl.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        assert_eq!(preview_end(&tl, &clock, None), ExitCode::SUCCESS);
+        assert_eq!(
+            end(&mut tl, &clock, &Config::default(), None, false),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            tl.day(jan2).unwrap().to_string(),
+            "2018/01/02 Tue | Work 00:00:00 01:30:00"
+        );
+        assert_eq!(
+            tl.day(jan2.pred()).unwrap().to_string(),
+            "2018/01/01 Mon | Work 22:00:00 00:00:00"
+        );
+        assert_eq!(
+            tl.day(jan2.pred())
+                .unwrap()
+                .logged_time(TimeLogEntryType::Work, Duration::hours(8)),
+            Duration::hours(2)
+        );
+
+        // An end given after midnight for the evening before
+        let mut tl: TimeLogger = "2018/01/01 Mon | Work 22:00:00 UNDEF".parse().unwrap();
+        tl.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        assert_eq!(
+            end(
+                &mut tl,
+                &clock,
+                &Config::default(),
+                Some(String::from("23:45")),
+                false
+            ),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            tl.day(jan2.pred()).unwrap().to_string(),
+            "2018/01/01 Mon | Work 22:00:00 23:45:00"
+        );
+
+        // Still refused when the end is before the start on the same side of midnight
+        let mut tl: TimeLogger = "2018/01/01 Mon | Work 22:00:00 UNDEF".parse().unwrap();
+        tl.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        assert_eq!(
+            end(
+                &mut tl,
+                &clock,
+                &Config::default(),
+                Some(String::from("21:00")),
+                false
+            ),
+            ExitCode::FAILURE
+        );
+    }
+
+    #[test]
+    fn end_preview() {
+        let contents = "2018/01/01 Mon | Work 08:00:00 UNDEF";
+        let tl: TimeLogger = contents.parse().unwrap();
+        let clock = FixedClock(NaiveDate::from_ymd(2018, 1, 1).and_hms(9, 30, 0));
+
+        assert_eq!(preview_end(&tl, &clock, None), ExitCode::SUCCESS);
+        assert_eq!(
+            preview_end(&tl, &clock, Some(String::from("07:00"))),
+            ExitCode::FAILURE
+        );
+        assert_eq!(tl.days()[0].to_string(), contents);
+
+        let tl: TimeLogger = "2018/01/01 Mon | Work 08:00:00 09:00:00".parse().unwrap();
+        assert_eq!(preview_end(&tl, &clock, None), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn start_end_guards() {
+        let contents = "2018/01/01 Mon | Work 08:00:00 UNDEF";
+        let mut tl: TimeLogger = contents.parse().unwrap();
+        let clock = FixedClock(NaiveDate::from_ymd(2018, 1, 1).and_hms(9, 0, 0));
+
+        assert_eq!(
+            start(&mut tl, &clock, None, false, false),
+            ExitCode::FAILURE
+        );
+        assert_eq!(tl.days()[0].entries().len(), 1);
+        assert_eq!(start(&mut tl, &clock, None, false, true), ExitCode::SUCCESS);
+        assert_eq!(tl.days()[0].entries().len(), 2);
+
+        let mut tl: TimeLogger = "2018/01/01 Mon | Work 08:00:00 12:00:00".parse().unwrap();
+        assert_eq!(
+            end(&mut tl, &clock, &Config::default(), None, false),
+            ExitCode::FAILURE
+        );
+        assert_eq!(
+            tl.days()[0].to_string(),
+            "2018/01/01 Mon | Work 08:00:00 12:00:00"
+        );
+    }
+
+    #[test]
+    fn start_end_record_history() {
+        let dir = std::env::temp_dir().join(format!(
+            "timelog_start_end_record_history_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut tl = TimeLogger::from_file(dir.join("log")).unwrap();
+        let clock = FixedClock(NaiveDate::from_ymd(2018, 1, 1).and_hms(16, 0, 0));
+
+        let start_time = Some(String::from("08:00"));
+        assert_eq!(
+            start(&mut tl, &clock, start_time, true, false),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            end(&mut tl, &clock, &Config::default(), None, true),
+            ExitCode::SUCCESS
+        );
+
+        let history = tl.history().unwrap();
+        let lines: Vec<&str> = history.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("| start 2018/01/01 08:00:00"));
+        assert!(lines[1].ends_with("| end 2018/01/01 16:00:00"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
-    fn parse_time() {
+    fn export_ics() {
+        let contents = "2018/01/01 Mon | Work 08:00:00 12:00:00
+2018/01/01 Mon | Work@acme;a,b\\c 13:00:00 16:00:00
+2018/01/02 Tue | Work 08:00:00 UNDEF
+2018/01/03 Wed | Vacation UNDEF UNDEF
+";
+        let tl: TimeLogger = contents.parse().unwrap();
+
+        let ics = write_ics(&tl.days(), tl.is_utc());
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT\r\n").count(), 2);
+        assert_eq!(ics.matches("END:VEVENT\r\n").count(), 2);
+        assert!(ics.contains(
+            "DTSTART:20180101T130000\r\nDTEND:20180101T160000\r\nSUMMARY:Work@acme\\;a\\,b\\\\c\r\n"
+        ));
+        for stamp in ics.lines().filter_map(|l| l.strip_prefix("DTSTAMP:")) {
+            assert!(chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%dT%H%M%SZ").is_ok());
+        }
+    }
+
+    #[test]
+    fn export_grouped_subtotals() {
+        let s = "2018/01/30 Tue | Work 08:00:00 16:00:00\n\
+                 2018/01/31 Wed | Work 08:00:00 12:30:00\n\
+                 2018/02/01 Thu | Work 08:00:00 16:00:00\n";
+        let tl: TimeLogger = s.parse().unwrap();
+        let groups = tl.grouped_totals(
+            NaiveDate::from_ymd(2018, 1, 1),
+            NaiveDate::from_ymd(2018, 2, 28),
+            Grouping::Month,
+        );
+
+        assert_eq!(
+            write_csv_grouped(&groups, ','),
+            "from,to,hours\r\n\
+             2018/01/01,2018/01/31,12;30\r\n\
+             2018/02/01,2018/02/28,8;0\r\n"
+        );
+        assert_eq!(
+            write_json_grouped(&groups),
+            r#"[{"from":"2018/01/01","to":"2018/01/31","total_min":750},{"from":"2018/02/01","to":"2018/02/28","total_min":480}]"#
+        );
+    }
+
+    #[test]
+    fn export_markdown() {
+        let s = "2018/01/01 Mon | Work 08:00:00 12:00:00\n\
+                 2018/01/01 Mon | Work@acme 13:00:00 16:30:00\n\
+                 2018/01/02 Tue | Work 08:00:00 UNDEF\n\
+                 2018/01/03 Wed | Vacation UNDEF UNDEF\n";
+        let tl: TimeLogger = s.parse().unwrap();
+        let days: Vec<&TimeLogDay> = tl
+            .days()
+            .into_iter()
+            .filter(|tld| tld.date() <= NaiveDate::from_ymd(2018, 1, 2))
+            .collect();
+
+        let md = write_markdown(&days);
+        let lines: Vec<&str> = md.lines().collect();
+        assert_eq!(lines[0], "| Date | Type | Start | End | Hours |");
+        assert_eq!(lines.len(), 2 + 3 + 1);
         assert_eq!(
-            parse_time_arg(&String::from("03:00")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            lines[3],
+            "| 2018/01/01 | Work@acme | 13:00 | 16:30 | 3;30 |"
         );
+        assert_eq!(lines[5], "| **Total** | | | | 7;30 |");
+    }
+
+    #[test]
+    fn reversed_date_range() {
+        let tl: TimeLogger = "2018/01/01 Mon | Work 08:00:00 12:00:00".parse().unwrap();
+        let (from, to) = ("2018/01/31", "2018/01/01");
+
+        assert!(matches!(
+            date_range_arg(&tl, from, to),
+            Err(TimeLogError::InvalidInputError(_))
+        ));
         assert_eq!(
-            parse_time_arg(&String::from("03.00")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            date_range_arg(&tl, to, from).unwrap(),
+            Some((
+                NaiveDate::from_ymd(2018, 1, 1),
+                NaiveDate::from_ymd(2018, 1, 31)
+            ))
         );
         assert_eq!(
-            parse_time_arg(&String::from("3.00")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            stats(&tl, &Config::default(), from, to, false),
+            ExitCode::FAILURE
         );
+        assert_eq!(export(&tl, "md", from, to, None), ExitCode::FAILURE);
         assert_eq!(
-            parse_time_arg(&String::from("3.0")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            export_grouped(&tl, "md", from, to, "week", None),
+            ExitCode::FAILURE
         );
+    }
+
+    #[test]
+    fn export_csv() {
+        assert_eq!(csv_field("acme", ','), "acme");
+        assert_eq!(csv_field("acme, inc", ','), "\"acme, inc\"");
+        assert_eq!(csv_field("acme, inc", ';'), "acme, inc");
         assert_eq!(
-            parse_time_arg(&String::from("03.0")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            csv_field("the \"acme\" job", ','),
+            "\"the \"\"acme\"\" job\""
         );
+        assert_eq!(csv_field("two\nlines", ','), "\"two\nlines\"");
+
+        let s = "2018/01/01 Mon | Work@acme 13:00:00 16:30:00\n\
+                 2018/01/03 Wed | Vacation UNDEF UNDEF\n";
+        let tl: TimeLogger = s.parse().unwrap();
         assert_eq!(
-            parse_time_arg(&String::from("3.00")),
-            Ok(NaiveTime::from_hms(3, 0, 0))
+            write_csv(&tl.days(), ';'),
+            "date;type;project;start;end;hours\r\n\
+             2018/01/01;Work;acme;13:00;16:30;\"3;30\"\r\n\
+             2018/01/03;Vacation;;;;\r\n"
         );
     }
+
+    #[test]
+    fn view_only_incomplete() {
+        let s = "2017/12/18 Mon | Work 08:00:00 16:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 UNDEF\n\
+                 2017/12/20 Wed | Work 08:00:00 UNDEF\n\
+                 2017/12/21 Thu | Work 08:00:00 16:00:00\n";
+        let tl: TimeLogger = s.parse().unwrap();
+
+        let mut opts = ViewOptions {
+            n_entries: Some(10),
+            ty: None,
+            index: false,
+            pager: false,
+            only_incomplete: true,
+        };
+        let days = view_days(&tl, &Config::default(), &opts);
+        assert_eq!(days.len(), 2);
+        assert!(days.iter().all(|tld| tld.has_unfinished_entries()));
+
+        // The latest day is complete, so the latest incomplete one is still shown
+        opts.n_entries = Some(1);
+        let days = view_days(&tl, &Config::default(), &opts);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].date(), NaiveDate::from_ymd(2017, 12, 20));
+    }
+
+    #[test]
+    fn view_count_precedence() {
+        assert_eq!(view_count(None, &Config::default()), 2);
+
+        let config: Config = "view.default_count = 7".parse().unwrap();
+        assert_eq!(view_count(None, &config), 7);
+        assert_eq!(view_count(Some(3), &config), 3);
+    }
 }
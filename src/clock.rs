@@ -0,0 +1,28 @@
+use chrono::{Local, NaiveDate, NaiveDateTime};
+
+// Source of the current local time, so that tests can run at a fixed point in time
+pub trait Clock {
+    fn now(&self) -> NaiveDateTime;
+
+    fn today(&self) -> NaiveDate {
+        self.now().date()
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+#[cfg(test)]
+pub struct FixedClock(pub NaiveDateTime);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
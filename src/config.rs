@@ -0,0 +1,252 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveTime};
+
+use crate::format::{parse_duration_arg, DurationFormat, InputFormats};
+
+use crate::timelog::{AutoLunch, TimeLogError, TimeLogResult, TimePrecision};
+use crate::timelogger::DEFAULT_MAX_BACKUPS;
+
+const CONFIG_FILE: &str = "config";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub view_default_count: usize,
+    pub storage_utc: bool,
+    pub batch_work_start: Option<NaiveTime>,
+    pub batch_work_end: Option<NaiveTime>,
+    pub max_daily_hours: Option<Duration>,
+    pub daily_loggable: Option<Duration>,
+    pub day_boundary: Option<NaiveTime>,
+    pub time_precision: TimePrecision,
+    pub lunch_deduct: Option<Duration>,
+    pub lunch_above: Option<Duration>,
+    pub max_backups: usize,
+    pub storage_weekday: bool,
+    pub flex_enabled: bool,
+    pub duration_format: DurationFormat,
+    pub input_formats: InputFormats,
+    pub locale: Option<String>,
+    pub round_display: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            view_default_count: 2,
+            storage_utc: false,
+            batch_work_start: None,
+            batch_work_end: None,
+            max_daily_hours: None,
+            daily_loggable: None,
+            day_boundary: None,
+            time_precision: TimePrecision::default(),
+            lunch_deduct: None,
+            lunch_above: None,
+            max_backups: DEFAULT_MAX_BACKUPS,
+            storage_weekday: true,
+            flex_enabled: true,
+            duration_format: DurationFormat::default(),
+            input_formats: InputFormats::default(),
+            locale: None,
+            round_display: None,
+        }
+    }
+}
+
+impl Config {
+    fn from_file(path: &Path) -> TimeLogResult<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        fs::read_to_string(path)?.parse()
+    }
+
+    // Only used when both the start and the end are configured
+    pub fn batch_work_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        self.batch_work_start.zip(self.batch_work_end)
+    }
+
+    // Off unless both the deduction and the threshold are configured
+    pub fn auto_lunch(&self) -> Option<AutoLunch> {
+        self.lunch_deduct
+            .zip(self.lunch_above)
+            .map(|(deduct, above)| AutoLunch { deduct, above })
+    }
+
+    pub fn load() -> TimeLogResult<Self> {
+        let dirs = directories::ProjectDirs::from("", "", "timelog")
+            .ok_or_else(|| TimeLogError::other_io("Can't find home dir"))?;
+        Config::from_file(dirs.config_dir().join(CONFIG_FILE).as_path())
+    }
+}
+
+// A comma separated list of chrono formats
+fn parse_formats(s: &str) -> TimeLogResult<Vec<String>> {
+    let formats: Vec<String> = s
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(String::from)
+        .collect();
+    match formats.is_empty() {
+        true => Err(TimeLogError::ParseError(String::from(
+            "Expected at least one format",
+        ))),
+        false => Ok(formats),
+    }
+}
+
+impl FromStr for Config {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<Config> {
+        let mut config = Config::default();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                TimeLogError::ParseError(format!("Expected 'key = value' in config: {}", line))
+            })?;
+
+            match key.trim() {
+                "view.default_count" => config.view_default_count = value.trim().parse()?,
+                "storage.utc" => config.storage_utc = value.trim().parse()?,
+                "storage.weekday" => config.storage_weekday = value.trim().parse()?,
+                "flex.enabled" => config.flex_enabled = value.trim().parse()?,
+                "batch.work_start" => {
+                    config.batch_work_start = Some(NaiveTime::parse_from_str(value.trim(), "%R")?)
+                }
+                "day.max_hours" => config.max_daily_hours = Some(parse_duration_arg(value.trim())?),
+                "day.loggable" => config.daily_loggable = Some(parse_duration_arg(value.trim())?),
+                "day.boundary" => {
+                    config.day_boundary = Some(NaiveTime::parse_from_str(value.trim(), "%R")?)
+                }
+                "lunch.deduct" => config.lunch_deduct = Some(parse_duration_arg(value.trim())?),
+                "lunch.above" => config.lunch_above = Some(parse_duration_arg(value.trim())?),
+                "backup.max_count" => config.max_backups = value.trim().parse()?,
+                "time.precision" => config.time_precision = value.trim().parse()?,
+                "input.date_formats" => config.input_formats.dates = parse_formats(value)?,
+                "input.time_formats" => config.input_formats.times = parse_formats(value)?,
+                "format.duration" => config.duration_format = value.trim().parse()?,
+                "display.locale" => config.locale = Some(value.trim().to_string()),
+                "display.round" => config.round_display = Some(parse_duration_arg(value.trim())?),
+                "batch.work_end" => {
+                    config.batch_work_end = Some(NaiveTime::parse_from_str(value.trim(), "%R")?)
+                }
+                k => {
+                    return Err(TimeLogError::ParseError(format!(
+                        "Unknown config key: {}",
+                        k
+                    )))
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_from_str() {
+        let s = "# Show a week of history\n\nview.default_count = 7\nstorage.utc = true\n";
+        let config: Config = s.parse().unwrap();
+        assert_eq!(config.view_default_count, 7);
+        assert!(config.storage_utc);
+
+        assert_eq!("".parse::<Config>().unwrap(), Config::default());
+        assert!("view.default_count".parse::<Config>().is_err());
+        assert!("view.count = 7".parse::<Config>().is_err());
+        assert!("view.default_count = seven".parse::<Config>().is_err());
+        assert!("storage.utc = yes".parse::<Config>().is_err());
+        assert!(
+            !"storage.weekday = false"
+                .parse::<Config>()
+                .unwrap()
+                .storage_weekday
+        );
+        assert!(
+            !"flex.enabled = false"
+                .parse::<Config>()
+                .unwrap()
+                .flex_enabled
+        );
+        assert!("batch.work_start = 8am".parse::<Config>().is_err());
+        assert!("day.max_hours = 10h".parse::<Config>().is_err());
+        assert!("time.precision = hours".parse::<Config>().is_err());
+        assert!("backup.max_count = -1".parse::<Config>().is_err());
+        assert!("format.duration = dots".parse::<Config>().is_err());
+
+        let config: Config = "time.precision = seconds".parse().unwrap();
+        assert_eq!(config.time_precision, TimePrecision::Seconds);
+
+        let config: Config = "input.date_formats = %Y-%m-%d, %Y/%m/%d".parse().unwrap();
+        assert_eq!(config.input_formats.dates, vec!["%Y-%m-%d", "%Y/%m/%d"]);
+        assert_eq!(config.input_formats.times, InputFormats::default().times);
+        assert!("input.time_formats = ,".parse::<Config>().is_err());
+
+        let config: Config = "display.locale = sv_SE".parse().unwrap();
+        assert_eq!(config.locale.as_deref(), Some("sv_SE"));
+
+        let config: Config = "format.duration = colon".parse().unwrap();
+        assert_eq!(config.duration_format, DurationFormat::Colon);
+
+        let config: Config = "day.boundary = 03:00".parse().unwrap();
+        assert_eq!(config.day_boundary, Some(NaiveTime::from_hms(3, 0, 0)));
+
+        let config: Config = "display.round = 0;15".parse().unwrap();
+        assert_eq!(config.round_display, Some(Duration::minutes(15)));
+
+        let config: Config = "day.loggable = 7;30".parse().unwrap();
+        assert_eq!(config.daily_loggable, Some(Duration::minutes(7 * 60 + 30)));
+
+        let config: Config = "day.max_hours = 10;30".parse().unwrap();
+        assert_eq!(
+            config.max_daily_hours,
+            Some(Duration::minutes(10 * 60 + 30))
+        );
+    }
+
+    #[test]
+    fn config_batch_work_hours() {
+        assert_eq!(Config::default().batch_work_hours(), None);
+
+        let config: Config = "batch.work_start = 08:00".parse().unwrap();
+        assert_eq!(config.batch_work_hours(), None);
+
+        let config: Config = "batch.work_start = 08:00\nbatch.work_end = 16:30"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.batch_work_hours(),
+            Some((NaiveTime::from_hms(8, 0, 0), NaiveTime::from_hms(16, 30, 0)))
+        );
+    }
+
+    #[test]
+    fn config_auto_lunch() {
+        assert_eq!(Config::default().auto_lunch(), None);
+
+        let config: Config = "lunch.deduct = 0;30".parse().unwrap();
+        assert_eq!(config.auto_lunch(), None);
+
+        let config: Config = "lunch.deduct = 0;30\nlunch.above = 6".parse().unwrap();
+        assert_eq!(
+            config.auto_lunch(),
+            Some(AutoLunch {
+                deduct: Duration::minutes(30),
+                above: Duration::hours(6),
+            })
+        );
+    }
+}
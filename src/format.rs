@@ -0,0 +1,236 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use chrono::{Duration, NaiveDate, NaiveTime, ParseResult};
+
+use crate::timelog::{TimeLogError, TimeLogResult};
+
+// How durations are printed, e.g. 7;30, 7:30 or 7h30m
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    Colon,
+    #[default]
+    Semicolon,
+    HoursMinutes,
+}
+
+impl DurationFormat {
+    pub fn format(&self, dur: Duration) -> String {
+        let (hours, minutes) = (dur.num_hours(), dur.num_minutes() % 60);
+        // Semicolon keeps the sign on both parts, as the log has always printed it
+        let sign = if dur < Duration::zero() { "-" } else { "" };
+        let (abs_hours, abs_minutes) = (hours.abs(), minutes.abs());
+        match self {
+            DurationFormat::Colon => format!("{}{}:{:02}", sign, abs_hours, abs_minutes),
+            DurationFormat::Semicolon => format!("{};{}", hours, minutes),
+            DurationFormat::HoursMinutes => format!("{}{}h{}m", sign, abs_hours, abs_minutes),
+        }
+    }
+}
+
+impl FromStr for DurationFormat {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<DurationFormat> {
+        match s {
+            "colon" => Ok(DurationFormat::Colon),
+            "semicolon" => Ok(DurationFormat::Semicolon),
+            "hm" => Ok(DurationFormat::HoursMinutes),
+            _ => Err(TimeLogError::ParseError(format!(
+                "Can't parse: {} as DurationFormat",
+                s
+            ))),
+        }
+    }
+}
+
+// Set once from the config at startup, durations are printed from too many places to thread it
+static DURATION_FORMAT: OnceLock<DurationFormat> = OnceLock::new();
+
+pub fn set_duration_format(format: DurationFormat) {
+    let _ = DURATION_FORMAT.set(format);
+}
+
+pub fn fmt_dur(dur: Duration) -> String {
+    DURATION_FORMAT
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .format(dur)
+}
+
+// The formats accepted for dates and times given as arguments, tried in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputFormats {
+    pub dates: Vec<String>,
+    pub times: Vec<String>,
+}
+
+impl Default for InputFormats {
+    fn default() -> Self {
+        InputFormats {
+            dates: vec![String::from("%Y/%m/%d")],
+            // %R = %H:%M
+            // %H: hour, two digits
+            // %M: minute, two digits
+            times: ["%R", "%H.%M", "%H"].map(String::from).to_vec(),
+        }
+    }
+}
+
+// The error is the one for the last format
+fn parse_first<T>(formats: &[String], parse: impl Fn(&str) -> ParseResult<T>) -> ParseResult<T> {
+    let mut err = None;
+    for f in formats {
+        match parse(f) {
+            Ok(x) => return Ok(x),
+            Err(e) => err = Some(e),
+        }
+    }
+
+    Err(err.expect("There is always at least one input format"))
+}
+
+impl InputFormats {
+    pub fn parse_date(&self, s: &str) -> ParseResult<NaiveDate> {
+        parse_first(&self.dates, |f| NaiveDate::parse_from_str(s, f))
+    }
+
+    pub fn parse_time(&self, s: &str) -> ParseResult<NaiveTime> {
+        parse_first(&self.times, |f| NaiveTime::parse_from_str(s, f))
+    }
+}
+
+// Set once from the config at startup, like the duration format
+static INPUT_FORMATS: OnceLock<InputFormats> = OnceLock::new();
+
+pub fn set_input_formats(formats: InputFormats) {
+    let _ = INPUT_FORMATS.set(formats);
+}
+
+pub fn parse_time_arg(s: &str) -> ParseResult<NaiveTime> {
+    match INPUT_FORMATS.get() {
+        Some(formats) => formats.parse_time(s),
+        None => InputFormats::default().parse_time(s),
+    }
+}
+
+pub fn parse_date_arg(s: &str) -> ParseResult<NaiveDate> {
+    match INPUT_FORMATS.get() {
+        Some(formats) => formats.parse_date(s),
+        None => InputFormats::default().parse_date(s),
+    }
+}
+
+// h;m as printed by fmt_dur, or whole hours. Durations are never negative.
+pub fn parse_duration_arg(s: &str) -> TimeLogResult<Duration> {
+    if s.starts_with('-') {
+        return Err(TimeLogError::inv_inp(
+            format!("Negative duration: {}", s).as_str(),
+        ));
+    }
+
+    let (hours, minutes) = s.split_once(';').unwrap_or((s, "0"));
+    let minutes: i64 = minutes.parse()?;
+    if !(0..60).contains(&minutes) {
+        return Err(TimeLogError::inv_inp(
+            format!("Invalid minutes in duration: {}", s).as_str(),
+        ));
+    }
+
+    Ok(Duration::hours(hours.parse()?) + Duration::minutes(minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_formats() {
+        let default = InputFormats::default();
+        assert_eq!(
+            default.parse_date("2018/01/02"),
+            Ok(NaiveDate::from_ymd(2018, 1, 2))
+        );
+        assert!(default.parse_date("2018-01-02").is_err());
+        assert!(default.parse_time("0830").is_err());
+
+        let iso = InputFormats {
+            dates: vec![String::from("%Y-%m-%d")],
+            times: vec![String::from("%R"), String::from("%H%M")],
+        };
+        assert_eq!(
+            iso.parse_date("2018-01-02"),
+            Ok(NaiveDate::from_ymd(2018, 1, 2))
+        );
+        assert!(iso.parse_date("2018/01/02").is_err());
+        assert_eq!(iso.parse_time("0830"), Ok(NaiveTime::from_hms(8, 30, 0)));
+        assert_eq!(iso.parse_time("08:30"), Ok(NaiveTime::from_hms(8, 30, 0)));
+        assert!(iso.parse_time("08.30").is_err());
+    }
+
+    #[test]
+    fn duration_format() {
+        let dur = Duration::minutes(450);
+        assert_eq!(DurationFormat::Semicolon.format(dur), "7;30");
+        assert_eq!(DurationFormat::Colon.format(dur), "7:30");
+        assert_eq!(DurationFormat::HoursMinutes.format(dur), "7h30m");
+        assert_eq!(DurationFormat::Colon.format(Duration::minutes(425)), "7:05");
+        let negative = -Duration::minutes(90);
+        assert_eq!(DurationFormat::Colon.format(negative), "-1:30");
+        assert_eq!(DurationFormat::HoursMinutes.format(negative), "-1h30m");
+        assert_eq!(DurationFormat::Colon.format(-Duration::minutes(5)), "-0:05");
+        assert_eq!(DurationFormat::Semicolon.format(negative), "-1;-30");
+
+        assert_eq!(DurationFormat::default(), DurationFormat::Semicolon);
+        assert_eq!(
+            "hm".parse::<DurationFormat>().unwrap(),
+            DurationFormat::HoursMinutes
+        );
+        assert!("h:m".parse::<DurationFormat>().is_err());
+    }
+
+    #[test]
+    fn parse_time() {
+        assert_eq!(
+            parse_time_arg(&String::from("03:00")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+        assert_eq!(
+            parse_time_arg(&String::from("03.00")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+        assert_eq!(
+            parse_time_arg(&String::from("3.00")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+        assert_eq!(
+            parse_time_arg(&String::from("3.0")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+        assert_eq!(
+            parse_time_arg(&String::from("03.0")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+        assert_eq!(
+            parse_time_arg(&String::from("3.00")),
+            Ok(NaiveTime::from_hms(3, 0, 0))
+        );
+    }
+
+    #[test]
+    fn parse_duration() {
+        assert_eq!(
+            parse_duration_arg("3;30"),
+            Ok(Duration::minutes(3 * 60 + 30))
+        );
+        assert_eq!(parse_duration_arg("3;0"), Ok(Duration::hours(3)));
+        assert_eq!(parse_duration_arg("3"), Ok(Duration::hours(3)));
+        assert!(parse_duration_arg("3;60").is_err());
+        assert!(parse_duration_arg("3:30").is_err());
+        assert!(parse_duration_arg("").is_err());
+        assert!(parse_duration_arg("-1;30").is_err());
+        assert!(parse_duration_arg("-0;30").is_err());
+        assert!(parse_duration_arg("-2").is_err());
+    }
+}
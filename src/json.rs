@@ -0,0 +1,80 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// Compact single-line JSON objects for machine-readable command output
+#[derive(Default)]
+pub struct JsonObject {
+    fields: Vec<(String, String)>,
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl JsonObject {
+    pub fn new() -> Self {
+        JsonObject::default()
+    }
+
+    pub fn str(mut self, key: &str, value: &str) -> Self {
+        self.fields.push((quote(key), quote(value)));
+        self
+    }
+
+    pub fn int(mut self, key: &str, value: i64) -> Self {
+        self.fields.push((quote(key), value.to_string()));
+        self
+    }
+
+    pub fn bool(mut self, key: &str, value: bool) -> Self {
+        self.fields.push((quote(key), value.to_string()));
+        self
+    }
+}
+
+impl Display for JsonObject {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i != 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_object() {
+        assert_eq!(JsonObject::new().to_string(), "{}");
+        let obj = JsonObject::new()
+            .str("since", "08:13")
+            .int("elapsed_min", -161);
+        assert_eq!(obj.to_string(), r#"{"since":"08:13","elapsed_min":-161}"#);
+        assert_eq!(
+            JsonObject::new().bool("clocked_in", false).to_string(),
+            r#"{"clocked_in":false}"#
+        );
+        assert_eq!(
+            JsonObject::new().str("a\"b", "c\\d\n").to_string(),
+            r#"{"a\"b":"c\\d\n"}"#
+        );
+    }
+}
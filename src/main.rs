@@ -1,13 +1,20 @@
 mod cli;
+mod clock;
+mod config;
+mod format;
+mod json;
 mod timelog;
 mod timelogger;
 
 use serde::Deserialize;
 
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::format::fmt_dur;
 use crate::timelog::TimeLogEntryType;
-use crate::timelogger::TimeLogger;
+use crate::timelogger::{logical_today, BatchOptions, Grouping, TimeLogger};
 use chrono::prelude::*;
-use chrono::Duration;
+use chrono::ParseResult;
 use docopt::Docopt;
 
 use std::process::ExitCode;
@@ -20,54 +27,141 @@ A commandline utility to log time. It maintains a human-editable time log
 in ~/.timelog.
 
 Usage:
-  timelog start [<time>]
-  timelog end [<time>]
-  timelog month [--with <time>]
-  timelog week [--with <time>]
-  timelog week [--last]
-  timelog day [--with <time>]
-  timelog day [--last]
-  timelog day [--mon | --tue | --wed | --thu | --fri]
-  timelog batch --from <from> --to <to> --type <type> [--weekday-only]
-  timelog view <n-entries>
+  timelog (start | in) [<time>] [--no-save] [--force] [--overwrite]
+  timelog (end | out) [<time>] [--no-save | --preview] [--overwrite]
+  timelog month [--with <time> | --for <duration>] [--round-display <duration>] [--project] [--format <format>]
+  timelog month --month-of <date> [--round-display <duration>] [--project] [--format <format>]
+  timelog week [--with <time> | --for <duration>] [--bar] [--round-display <duration>] [--project] [--format <format>]
+  timelog week [--last | --week-of <date>] [--bar] [--round-display <duration>] [--project] [--format <format>]
+  timelog day [--with <time> | --for <duration>] [--format <format>]
+  timelog day [--last] [--format <format>]
+  timelog day [--mon | --tue | --wed | --thu | --fri] [--next] [--format <format>]
+  timelog batch --from <from> --to <to> --type <type> [--weekday-only] [--hours-per-day <duration>] [--skip-existing] [--overwrite]
+  timelog view [<n-entries>] [--type <type>] [--index] [--no-pager] [--only-incomplete]
+  timelog recent [<n-entries>]
+  timelog summary [--by-project] [--after <time>] [--before <time>] [--round-display <duration>]
+  timelog stats [--from <from> --to <to>] [--flex-trend]
+  timelog project rename <old> <new> [--force] [--overwrite]
+  timelog delete <date> <index> [--overwrite]
+  timelog delete <date> --type <type> [--overwrite]
+  timelog flex [--format <format>]
+  timelog flex reset [<date>] [--overwrite]
+  timelog export --format <format> [--from <from> --to <to>] [--group-by <group>] [--csv-delimiter <char>]
+  timelog diff <other>
+  timelog normalize [--force] [--overwrite] [--dry-run]
+  timelog compact [--dry-run] [--force] [--overwrite]
+  timelog undo
+  timelog history
+  timelog clear [--yes] [--overwrite]
+  timelog migrate --to-utc [--force] [--overwrite]
   timelog (-h | --help)
 
 Options:
   -h, --help                Show this screen.
   -w, --with <time>         If there is no end time for an entry, this will be used instead.
+  --for <duration>          Count the entry without an end time as <duration> (h;m) long.
+  --week-of <date>          Show the week containing <date> (YYYY/MM/DD).
+  --month-of <date>         Show the month containing <date> (YYYY/MM/DD).
+  --type <type>             Entry type: Work, Overtime, Holiday, Sickness, Vacation or ParentalLeave.
+  --round-display <duration>  Round the time worked in the period to the nearest multiple of <duration> (h;m),
+                            overrides display.round in the config.
+  --project                 Show where the period ends if the rest of its workdays are worked at the average so far.
+  --hours-per-day <duration>  Length (h;m) of each batched day, from the configured work start.
+  --after <time>            Only count the time after <time> of each day.
+  --before <time>           Only count the time before <time> of each day.
+  --group-by <group>        Export totals per week or month instead of entries.
+  --format <format>         Output format: ics, md or csv for export, md, csv or json for a
+                            grouped export, json for flex, day, week and month.
+  --csv-delimiter <char>    Field delimiter of the csv export, defaults to a comma.
+  --dry-run                 Show what would change in the logfile without saving.
+  --force                   Start even while an entry is open, or save even if the log would end up empty.
+  --overwrite               Save even if the logfile was modified on disk after it was loaded.
+
+Examples:
+  timelog start 08:00
+  timelog end --preview
+  timelog week --last --bar
+  timelog day --for '3;30'
+  timelog batch --from 2018/07/02 --to 2018/07/23 --type Vacation --weekday-only
+  timelog export --format csv --csv-delimiter ';' --from 2018/01/01 --to 2018/01/31
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
     cmd_start: bool,
+    cmd_in: bool,
     cmd_end: bool,
+    cmd_out: bool,
+    flag_no_save: bool,
+    flag_preview: bool,
     cmd_month: bool,
     cmd_week: bool,
+    flag_bar: bool,
+    flag_round_display: Option<String>,
+    flag_project: bool,
     cmd_day: bool,
     cmd_view: bool,
+    flag_index: bool,
+    flag_no_pager: bool,
+    flag_only_incomplete: bool,
+    cmd_recent: bool,
+    cmd_summary: bool,
+    flag_by_project: bool,
+    flag_after: Option<String>,
+    flag_before: Option<String>,
+    cmd_stats: bool,
+    flag_flex_trend: bool,
+    cmd_project: bool,
+    cmd_rename: bool,
+    arg_old: String,
+    arg_new: String,
+    cmd_delete: bool,
+    arg_index: Option<usize>,
+    cmd_flex: bool,
+    cmd_reset: bool,
+    arg_date: Option<String>,
+    cmd_export: bool,
+    flag_group_by: Option<String>,
+    flag_format: Option<String>,
+    flag_csv_delimiter: Option<String>,
+    cmd_diff: bool,
+    arg_other: String,
+    cmd_normalize: bool,
+    flag_force: bool,
+    flag_overwrite: bool,
+    flag_dry_run: bool,
+    cmd_compact: bool,
+    cmd_undo: bool,
+    cmd_history: bool,
+    cmd_clear: bool,
+    flag_yes: bool,
+    cmd_migrate: bool,
+    flag_to_utc: bool,
     arg_time: Option<String>,
-    arg_n_entries: usize,
+    arg_n_entries: Option<usize>,
     flag_with: Option<String>,
+    flag_for: Option<String>,
     flag_last: bool,
+    flag_week_of: Option<String>,
+    flag_month_of: Option<String>,
     flag_mon: bool,
     flag_tue: bool,
     flag_wed: bool,
     flag_thu: bool,
     flag_fri: bool,
+    flag_next: bool,
     // batch
     cmd_batch: bool,
     arg_from: String,
     arg_to: String,
-    arg_type: String,
+    flag_type: Option<String>,
     flag_weekday_only: bool,
+    flag_hours_per_day: Option<String>,
+    flag_skip_existing: bool,
 }
 
-fn fmt_dur(dur: Duration) -> String {
-    format!("{};{}", dur.num_hours(), dur.num_minutes() % 60)
-}
-
-fn get_date_for_day_cmd(args: &Args) -> NaiveDate {
-    let mut date = Local::today().naive_local();
+fn get_date_for_day_cmd(args: &Args, clock: &dyn Clock, boundary: Option<NaiveTime>) -> NaiveDate {
+    let mut date = logical_today(clock.now(), boundary);
     let mut target = date.weekday();
     if args.flag_mon {
         target = Weekday::Mon;
@@ -83,34 +177,46 @@ fn get_date_for_day_cmd(args: &Args) -> NaiveDate {
         target = date.pred().weekday();
     };
 
+    // Searches backwards unless asked for the upcoming day
     while date.weekday() != target {
-        date = date.pred();
+        date = match args.flag_next {
+            true => date.succ(),
+            false => date.pred(),
+        };
     }
 
     date
 }
 
 fn get_text_for_day_cmd(args: &Args) -> String {
-    let ret: &str;
+    let day: &str;
     if args.flag_mon {
-        ret = "last monday";
+        day = "monday";
     } else if args.flag_tue {
-        ret = "last tuesday";
+        day = "tuesday";
     } else if args.flag_wed {
-        ret = "last wednesday";
+        day = "wednesday";
     } else if args.flag_thu {
-        ret = "last thursday";
+        day = "thursday";
     } else if args.flag_fri {
-        ret = "last friday";
+        day = "friday";
     } else if args.flag_last {
-        ret = "yesterday"
+        return "yesterday".to_string();
     } else {
-        ret = "today";
+        return "today".to_string();
+    }
+
+    match args.flag_next {
+        true => format!("next {}", day),
+        false => format!("last {}", day),
     }
-    ret.to_string()
 }
 
 fn get_text_for_monthweek_cmd(args: &Args) -> String {
+    if args.flag_week_of.is_some() || args.flag_month_of.is_some() {
+        return "that".to_string();
+    }
+
     match args.flag_last {
         true => "last",
         false => "this",
@@ -118,8 +224,12 @@ fn get_text_for_monthweek_cmd(args: &Args) -> String {
     .to_string()
 }
 
-fn get_date_for_week_cmd(args: &Args) -> NaiveDate {
-    let mut date = Local::today().naive_local();
+fn get_date_for_week_cmd(args: &Args, clock: &dyn Clock) -> ParseResult<NaiveDate> {
+    if let Some(date) = &args.flag_week_of {
+        return format::parse_date_arg(date);
+    }
+
+    let mut date = clock.today();
     let target = date.weekday();
     if args.flag_last {
         date = date.pred();
@@ -129,21 +239,39 @@ fn get_date_for_week_cmd(args: &Args) -> NaiveDate {
         date = date.pred();
     }
 
-    date
+    Ok(date)
 }
 
-fn get_date_for_month_cmd(args: &Args) -> NaiveDate {
-    let today = Local::today().naive_local();
-    match args.flag_last {
+fn get_date_for_month_cmd(args: &Args, clock: &dyn Clock) -> ParseResult<NaiveDate> {
+    if let Some(date) = &args.flag_month_of {
+        return format::parse_date_arg(date);
+    }
+
+    let today = clock.today();
+    Ok(match args.flag_last {
         false => today,
         true => NaiveDate::from_ymd(today.year(), today.month(), 1).pred(),
-    }
+    })
 }
 
 fn main() -> ExitCode {
-    let args: Args = Docopt::new(USAGE)
-        .and_then(|d| d.deserialize())
-        .unwrap_or_else(|e| e.exit());
+    let args: Args = match Docopt::new(USAGE).and_then(|d| d.deserialize()) {
+        Ok(x) => x,
+        // --help and --version are reported as non-fatal errors
+        Err(e) if !e.fatal() => e.exit(),
+        Err(e) => {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let config = match Config::load() {
+        Ok(x) => x,
+        Err(e) => {
+            println!("ERROR: Could not load config: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
     let mut tl = match TimeLogger::default() {
         Ok(x) => x,
@@ -153,13 +281,76 @@ fn main() -> ExitCode {
         }
     };
 
-    if args.cmd_start {
-        return cli::start(&mut tl, args.arg_time);
-    } else if args.cmd_end {
-        return cli::end(&mut tl, args.arg_time);
+    if config.storage_utc && !tl.is_utc() && tl.set_utc().is_err() {
+        println!("WARN: UTC storage is configured but the logfile is in local time, run `timelog migrate --to-utc`");
+    }
+
+    tl.set_time_precision(config.time_precision);
+    tl.set_auto_lunch(config.auto_lunch());
+    tl.set_max_backups(config.max_backups);
+    tl.set_write_weekday(config.storage_weekday);
+    tl.set_flex_enabled(config.flex_enabled);
+    tl.set_overwrite_changes(args.flag_overwrite);
+    tl.set_allow_empty(args.flag_force);
+    format::set_duration_format(config.duration_format);
+    format::set_input_formats(config.input_formats.clone());
+    if let Some(locale) = config.locale.as_deref() {
+        // Only the weekday names depend on it, so the commands still work without it
+        if let Err(e) = cli::set_locale(locale) {
+            println!("WARNING: Ignoring display.locale: {}", e);
+        }
+    }
+    tl.set_day_boundary(config.day_boundary);
+    if let Some(daily_loggable) = config.daily_loggable {
+        tl.set_daily_loggable(daily_loggable);
+    }
+
+    let clock = SystemClock;
+    let round = match args
+        .flag_round_display
+        .as_deref()
+        .map(format::parse_duration_arg)
+        .transpose()
+    {
+        Ok(x) => x.or(config.round_display),
+        Err(e) => {
+            println!("Failed to parse duration for --round-display: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Other commands take other formats for --format
+    let json = match cli::json_format_arg(args.flag_format.as_deref()) {
+        Ok(x) => x,
+        Err(e) if args.cmd_day || args.cmd_week || args.cmd_month => {
+            println!("Invalid --format: {}", e);
+            return ExitCode::FAILURE;
+        }
+        Err(_) => false,
+    };
+
+    if args.cmd_start || args.cmd_in {
+        return cli::start(
+            &mut tl,
+            &clock,
+            args.arg_time,
+            !args.flag_no_save,
+            args.flag_force,
+        );
+    } else if (args.cmd_end || args.cmd_out) && args.flag_preview {
+        return cli::preview_end(&tl, &clock, args.arg_time);
+    } else if args.cmd_end || args.cmd_out {
+        return cli::end(&mut tl, &clock, &config, args.arg_time, !args.flag_no_save);
     } else if args.cmd_month {
-        let date = get_date_for_month_cmd(&args);
-        let time = match cli::get_time(args.flag_with) {
+        let date = match get_date_for_month_cmd(&args, &clock) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Failed to parse NaiveDate for --month-of: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        let month_text_fmt = get_text_for_monthweek_cmd(&args);
+        let time = match cli::get_with_time(&tl, &clock, args.flag_with, args.flag_for) {
             Ok(t) => t,
             Err(e) => {
                 println!("Unable to parse args: {}", e);
@@ -167,12 +358,10 @@ fn main() -> ExitCode {
             }
         };
 
-        let this_month = !args.flag_last;
-        if !this_month {
+        let this_month = !(args.flag_last || args.flag_month_of.is_some());
+        if !this_month && !json {
             if let Some(bad_entries) = tl.verify_entries_in_month_of(date) {
-                for e in bad_entries {
-                    println!("Incomplete entry: {}", e);
-                }
+                cli::print_incomplete(&tl, &bad_entries);
             }
         }
 
@@ -181,30 +370,41 @@ fn main() -> ExitCode {
             false => None,
         };
 
-        let (time_left, _) = match tl.time_left_in_month_of_with(date, time_opt) {
+        let summary = match tl.month_summary_with(date, time_opt) {
             Ok(x) => x,
             Err(e) => {
-                println!("Couldn't calculate time left this week: {}", e);
+                println!("Couldn't calculate time {} month: {}", month_text_fmt, e);
                 return ExitCode::FAILURE;
             }
         };
-        let time_worked = match tl.time_logged_in_month_of_with(date, time_opt) {
-            Ok(x) => x,
-            Err(e) => {
-                println!("Couldn't calculate time worked this week: {}", e);
-                return ExitCode::FAILURE;
-            }
+        let projection = match args.flag_project {
+            true => Some(tl.project_period(date, Grouping::Month)),
+            false => None,
         };
+        if json {
+            println!("{}", cli::period_summary_json(&summary, round, projection));
+            return ExitCode::SUCCESS;
+        }
 
         println!(
-            "{} worked this month\n{} left this month",
-            fmt_dur(time_worked),
-            fmt_dur(time_left)
+            "{}\n{} left {} month",
+            cli::fmt_worked(&summary, round, &month_text_fmt, "month"),
+            fmt_dur(summary.remaining),
+            month_text_fmt
         );
+        if let Some(projection) = projection {
+            cli::print_projection(projection, "month");
+        }
     } else if args.cmd_week {
-        let date = get_date_for_week_cmd(&args);
+        let date = match get_date_for_week_cmd(&args, &clock) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Failed to parse NaiveDate for --week-of: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
         let week_text_fmt = get_text_for_monthweek_cmd(&args);
-        let time = match cli::get_time(args.flag_with) {
+        let time = match cli::get_with_time(&tl, &clock, args.flag_with, args.flag_for) {
             Ok(t) => t,
             Err(e) => {
                 println!("Unable to parse args: {}", e);
@@ -212,12 +412,10 @@ fn main() -> ExitCode {
             }
         };
 
-        let this_week = !args.flag_last;
-        if !this_week {
+        let this_week = !(args.flag_last || args.flag_week_of.is_some());
+        if !this_week && !json {
             if let Some(bad_entries) = tl.verify_entries_in_week_of(date) {
-                for e in bad_entries {
-                    println!("Incomplete entry: {}", e);
-                }
+                cli::print_incomplete(&tl, &bad_entries);
             }
         }
 
@@ -226,33 +424,49 @@ fn main() -> ExitCode {
             false => None,
         };
 
-        let (time_left, flex) = match tl.time_left_in_week_of_with(date, time_opt) {
+        let summary = match tl.week_summary_with(date, time_opt) {
             Ok(x) => x,
             Err(e) => {
-                println!("Couldn't calculate time left {} week: {}", week_text_fmt, e);
+                println!("Couldn't calculate time {} week: {}", week_text_fmt, e);
                 return ExitCode::FAILURE;
             }
         };
-        let time_worked = match tl.time_logged_in_week_of_with(date, time_opt) {
-            Ok(x) => x,
-            Err(e) => {
-                println!(
-                    "Couldn't calculate time worked {} week: {}",
-                    week_text_fmt, e
-                );
-                return ExitCode::FAILURE;
-            }
+        let projection = match args.flag_project {
+            true => Some(tl.project_period(date, Grouping::Week)),
+            false => None,
         };
+        if json {
+            println!("{}", cli::period_summary_json(&summary, round, projection));
+            return ExitCode::SUCCESS;
+        }
 
         println!(
-            "{0} worked {3} week\n{1} left {3} week ({2} of which is flex)",
-            fmt_dur(time_worked),
-            fmt_dur(time_left),
-            fmt_dur(flex),
-            week_text_fmt
+            "{}\n{}",
+            cli::week_header(date),
+            cli::fmt_worked(&summary, round, &week_text_fmt, "week")
         );
+        match config.flex_enabled {
+            true => println!(
+                "{0} left {2} week ({1} of which is flex)",
+                fmt_dur(summary.remaining),
+                fmt_dur(summary.flex),
+                week_text_fmt
+            ),
+            false => println!("{} left {} week", fmt_dur(summary.remaining), week_text_fmt),
+        }
+        if args.flag_bar {
+            println!(
+                "{} {}/{}",
+                cli::progress_bar(summary.logged, summary.loggable, 20),
+                fmt_dur(summary.logged),
+                fmt_dur(summary.loggable)
+            );
+        }
+        if let Some(projection) = projection {
+            cli::print_projection(projection, "week");
+        }
     } else if args.cmd_day {
-        let date = get_date_for_day_cmd(&args);
+        let date = get_date_for_day_cmd(&args, &clock, config.day_boundary);
         let day_text_fmt = get_text_for_day_cmd(&args);
         let today = !(args.flag_last
             || args.flag_mon
@@ -261,7 +475,8 @@ fn main() -> ExitCode {
             || args.flag_thu
             || args.flag_fri);
 
-        let time = match cli::get_time(args.flag_with) {
+        let now_summary = args.flag_with.is_none() && args.flag_for.is_none();
+        let time = match cli::get_with_time(&tl, &clock, args.flag_with, args.flag_for) {
             Ok(t) => t,
             Err(e) => {
                 println!("Unable to parse args: {}", e);
@@ -269,25 +484,126 @@ fn main() -> ExitCode {
             }
         };
 
-        let time_opt = match today {
-            true => Some(time),
-            false => None,
-        };
+        if today {
+            let summary = match now_summary {
+                true => tl.status(&clock),
+                false => tl.today_summary(date, Some(time)),
+            };
+            let summary = match summary {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Couldn't calculate time worked {}: {}", day_text_fmt, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            cli::warn_overtime(&tl, &config, date, Some(time));
+            if json {
+                println!("{}", cli::day_summary_json(&summary, time));
+                return ExitCode::SUCCESS;
+            }
+            println!("{} worked {}", fmt_dur(summary.worked), day_text_fmt);
+            println!("{} left {}", fmt_dur(summary.remaining), day_text_fmt);
+            if let Some(start) = summary.open_since {
+                println!("Working since {}", start.format("%R"));
+            }
+            return ExitCode::SUCCESS;
+        }
 
-        let worked_time = match tl.time_logged_at_date_with(date, time_opt) {
+        let worked_time = match tl.time_logged_at_date_with(date, None) {
             Ok(x) => x,
             Err(e) => {
                 println!("Couldn't calculate time worked {}: {}", day_text_fmt, e);
                 return ExitCode::FAILURE;
             }
         };
+        if json {
+            println!("{}", cli::past_day_json(date, worked_time));
+            return ExitCode::SUCCESS;
+        }
         println!("{} worked {}", fmt_dur(worked_time), day_text_fmt);
     } else if args.cmd_view {
-        for tld in tl.get_latest_n_entries(args.arg_n_entries) {
-            println!("{}", tld);
+        let ty = match args.flag_type.as_deref().map(TimeLogEntryType::from_str) {
+            None => None,
+            Some(Ok(x)) => Some(x),
+            Some(Err(e)) => {
+                println!("Failed to parse TimeLogEntryType for --type: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        let opts = cli::ViewOptions {
+            n_entries: args.arg_n_entries,
+            ty,
+            index: args.flag_index,
+            pager: !args.flag_no_pager,
+            only_incomplete: args.flag_only_incomplete,
+        };
+        return cli::view(&tl, &config, &opts);
+    } else if args.cmd_recent {
+        return cli::recent(&tl, &config, args.arg_n_entries);
+    } else if args.cmd_summary {
+        let window =
+            match cli::time_window_arg(args.flag_after.as_deref(), args.flag_before.as_deref()) {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Invalid --after/--before: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+        return cli::summary(&tl, args.flag_by_project, window, round);
+    } else if args.cmd_stats {
+        return cli::stats(
+            &tl,
+            &config,
+            &args.arg_from,
+            &args.arg_to,
+            args.flag_flex_trend,
+        );
+    } else if args.cmd_project && args.cmd_rename {
+        return cli::rename_project(&mut tl, &args.arg_old, &args.arg_new);
+    } else if args.cmd_migrate && args.flag_to_utc {
+        return cli::migrate_to_utc(&mut tl);
+    } else if args.cmd_delete {
+        let date = args.arg_date.unwrap_or_default();
+        if let Some(ty) = args.flag_type.as_deref() {
+            return cli::delete_type(&mut tl, &date, ty);
+        }
+        return cli::delete(&mut tl, &date, args.arg_index.unwrap_or_default());
+    } else if args.cmd_flex && args.cmd_reset {
+        return cli::reset_flex(&mut tl, args.arg_date);
+    } else if args.cmd_flex {
+        return cli::flex(&tl, &clock, args.flag_format.as_deref().unwrap_or_default());
+    } else if args.cmd_export {
+        if let Some(group_by) = args.flag_group_by.as_deref() {
+            return cli::export_grouped(
+                &tl,
+                args.flag_format.as_deref().unwrap_or_default(),
+                &args.arg_from,
+                &args.arg_to,
+                group_by,
+                args.flag_csv_delimiter.as_deref(),
+            );
         }
+        return cli::export(
+            &tl,
+            args.flag_format.as_deref().unwrap_or_default(),
+            &args.arg_from,
+            &args.arg_to,
+            args.flag_csv_delimiter.as_deref(),
+        );
+    } else if args.cmd_diff {
+        return cli::diff(&tl, &args.arg_other);
+    } else if args.cmd_normalize {
+        return cli::normalize(&mut tl, args.flag_dry_run);
+    } else if args.cmd_compact {
+        return cli::compact(&mut tl, args.flag_dry_run);
+    } else if args.cmd_undo {
+        return cli::undo(&tl);
+    } else if args.cmd_history {
+        return cli::history(&tl);
+    } else if args.cmd_clear {
+        return cli::clear(&mut tl, args.flag_yes);
     } else if args.cmd_batch {
-        let ty = match TimeLogEntryType::from_str(args.arg_type.as_str()) {
+        let ty = match TimeLogEntryType::from_str(args.flag_type.as_deref().unwrap_or_default()) {
             Ok(x) => x,
             Err(e) => {
                 println!("Failed to parse TimeLogEntryType for --type: {}", e);
@@ -295,9 +611,7 @@ fn main() -> ExitCode {
             }
         };
 
-        let naive_date_str = "%Y/%m/%d";
-
-        let from = match NaiveDate::parse_from_str(args.arg_from.as_str(), naive_date_str) {
+        let from = match format::parse_date_arg(&args.arg_from) {
             Ok(x) => x,
             Err(e) => {
                 println!("Failed to parse NaiveDate for --from: {}", e);
@@ -305,7 +619,7 @@ fn main() -> ExitCode {
             }
         };
 
-        let to = match NaiveDate::parse_from_str(args.arg_to.as_str(), naive_date_str) {
+        let to = match format::parse_date_arg(&args.arg_to) {
             Ok(x) => x,
             Err(e) => {
                 println!("Failed to parse NaiveDate for --to: {}", e);
@@ -313,16 +627,184 @@ fn main() -> ExitCode {
             }
         };
 
-        if let Err(e) = tl.batch_add(ty, from, to, args.flag_weekday_only) {
-            println!("Batch command failed: {}", e);
+        let hours_per_day = match args
+            .flag_hours_per_day
+            .as_deref()
+            .map(format::parse_duration_arg)
+        {
+            None => None,
+            Some(Ok(x)) => Some(x),
+            Some(Err(e)) => {
+                println!("Failed to parse duration for --hours-per-day: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let opts = BatchOptions {
+            weekday_only: args.flag_weekday_only,
+            work_hours: config.batch_work_hours(),
+            hours_per_day,
+            skip_existing: args.flag_skip_existing,
+        };
+        match tl.batch_add(ty, from, to, &opts) {
+            Ok(counts) if opts.skip_existing => println!(
+                "Added {} days, skipped {} days with existing entries",
+                counts.added, counts.skipped
+            ),
+            Ok(_) => (),
+            Err(e) => {
+                println!("Batch command failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+
+        if let Err(e) = tl.save() {
+            println!("Failed to save to logfile: {}", e);
             return ExitCode::FAILURE;
         }
+        cli::record(&tl, format!("batch {} to {}", args.arg_from, args.arg_to));
     }
 
-    if let Err(e) = tl.save() {
-        println!("Failed to save to logfile: {}", e);
-        return ExitCode::FAILURE;
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+
+    fn parse_args(argv: &[&str]) -> Result<Args, docopt::Error> {
+        Docopt::new(USAGE).and_then(|d| d.argv(argv).deserialize())
     }
 
-    ExitCode::SUCCESS
+    #[test]
+    fn usage_examples_parse() {
+        let examples: Vec<&str> = USAGE
+            .lines()
+            .skip_while(|l| *l != "Examples:")
+            .skip(1)
+            .take_while(|l| !l.is_empty())
+            .collect();
+        assert_eq!(examples.len(), 6);
+        for example in examples {
+            let argv: Vec<&str> = example.split_whitespace().collect();
+            assert!(parse_args(&argv).is_ok(), "{}", example);
+        }
+    }
+
+    #[test]
+    fn in_and_out_aliases() {
+        let args = parse_args(&["timelog", "in", "08:00"]).unwrap();
+        assert!(args.cmd_in && !args.cmd_start);
+        assert_eq!(args.arg_time.as_deref(), Some("08:00"));
+
+        let args = parse_args(&["timelog", "out", "--no-save"]).unwrap();
+        assert!(args.cmd_out && args.flag_no_save);
+        assert_eq!(args.arg_time, None);
+
+        let args = parse_args(&["timelog", "start", "--force"]).unwrap();
+        assert!(args.cmd_start && args.flag_force);
+    }
+
+    #[test]
+    fn format_on_summaries() {
+        for argv in [
+            &["timelog", "day", "--format", "json"][..],
+            &["timelog", "day", "--last", "--format", "json"],
+            &["timelog", "week", "--last", "--format", "json"],
+            &["timelog", "month", "--for", "1;00", "--format", "json"],
+        ] {
+            let args = parse_args(argv).unwrap();
+            assert_eq!(args.flag_format.as_deref(), Some("json"), "{:?}", argv);
+        }
+    }
+
+    #[test]
+    fn overwrite_on_saving_commands() {
+        for argv in [
+            &["timelog", "end", "16:00", "--overwrite"][..],
+            &["timelog", "delete", "2018/01/01", "0", "--overwrite"],
+            &[
+                "timelog",
+                "delete",
+                "2018/01/01",
+                "--type",
+                "Work",
+                "--overwrite",
+            ],
+            &["timelog", "project", "rename", "a", "b", "--overwrite"],
+            &["timelog", "flex", "reset", "--overwrite"],
+            &["timelog", "compact", "--overwrite"],
+            &["timelog", "migrate", "--to-utc", "--overwrite"],
+        ] {
+            assert!(parse_args(argv).unwrap().flag_overwrite, "{:?}", argv);
+        }
+        assert!(parse_args(&["timelog", "week", "--overwrite"]).is_err());
+        assert!(parse_args(&["timelog", "end", "--force"]).is_err());
+        assert!(
+            parse_args(&["timelog", "compact", "--force"])
+                .unwrap()
+                .flag_force
+        );
+        assert!(
+            parse_args(&["timelog", "normalize", "--force"])
+                .unwrap()
+                .flag_force
+        );
+    }
+
+    #[test]
+    fn with_and_for_are_exclusive() {
+        let args = parse_args(&["timelog", "day", "--for", "3;30"]).unwrap();
+        assert_eq!(args.flag_for.as_deref(), Some("3;30"));
+        assert!(parse_args(&["timelog", "day", "--with", "16:00", "--for", "3;30"]).is_err());
+    }
+
+    #[test]
+    fn day_next_weekday() {
+        // A wednesday
+        let clock = FixedClock(NaiveDate::from_ymd(2018, 1, 10).and_hms(12, 0, 0));
+
+        let args = parse_args(&["timelog", "day", "--mon", "--next"]).unwrap();
+        assert_eq!(
+            get_date_for_day_cmd(&args, &clock, None),
+            NaiveDate::from_ymd(2018, 1, 15)
+        );
+        assert_eq!(get_text_for_day_cmd(&args), "next monday");
+
+        let args = parse_args(&["timelog", "day", "--mon"]).unwrap();
+        assert_eq!(
+            get_date_for_day_cmd(&args, &clock, None),
+            NaiveDate::from_ymd(2018, 1, 8)
+        );
+        assert_eq!(get_text_for_day_cmd(&args), "last monday");
+    }
+
+    #[test]
+    fn week_of_and_month_of() {
+        let clock = FixedClock(NaiveDate::from_ymd(2018, 1, 10).and_hms(12, 0, 0));
+
+        let args = parse_args(&["timelog", "week", "--week-of", "2017/12/20"]).unwrap();
+        assert_eq!(
+            get_date_for_week_cmd(&args, &clock),
+            Ok(NaiveDate::from_ymd(2017, 12, 20))
+        );
+        assert_eq!(get_text_for_monthweek_cmd(&args), "that");
+
+        let args = parse_args(&["timelog", "week", "--last"]).unwrap();
+        assert_eq!(
+            get_date_for_week_cmd(&args, &clock),
+            Ok(NaiveDate::from_ymd(2018, 1, 3))
+        );
+
+        let args = parse_args(&["timelog", "month", "--month-of", "2017/12/20"]).unwrap();
+        assert_eq!(
+            get_date_for_month_cmd(&args, &clock),
+            Ok(NaiveDate::from_ymd(2017, 12, 20))
+        );
+
+        assert!(parse_args(&["timelog", "week", "--last", "--week-of", "2017/12/20"]).is_err());
+        let args = parse_args(&["timelog", "week", "--week-of", "20/12/2017"]).unwrap();
+        assert!(get_date_for_week_cmd(&args, &clock).is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt;
@@ -46,7 +47,16 @@ impl TimeLogError {
     }
 }
 
-impl Error for TimeLogError {}
+impl Error for TimeLogError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TimeLogError::IOError(ref err) => Some(err),
+            TimeLogError::TimeError(ref err) => Some(err),
+            TimeLogError::ParseError(_) | TimeLogError::InvalidInputError(_) => None,
+        }
+    }
+}
+
 impl Display for TimeLogError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -76,6 +86,12 @@ impl From<std::num::ParseIntError> for TimeLogError {
     }
 }
 
+impl From<std::str::ParseBoolError> for TimeLogError {
+    fn from(err: std::str::ParseBoolError) -> TimeLogError {
+        TimeLogError::ParseError(format!("{}", err))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub enum TimeLogEntryType {
     Work,
@@ -83,22 +99,97 @@ pub enum TimeLogEntryType {
     Sickness,
     Vacation,
     ParentalLeave,
+    Overtime,
 }
 
 impl TimeLogEntryType {
-    const ETYPES: [TimeLogEntryType; 5] = [
+    const ETYPES: [TimeLogEntryType; 6] = [
         TimeLogEntryType::Work,
+        TimeLogEntryType::Overtime,
         TimeLogEntryType::Sickness,
         TimeLogEntryType::Vacation,
         TimeLogEntryType::ParentalLeave,
         TimeLogEntryType::Holiday,
     ];
 
+    pub fn all() -> &'static [TimeLogEntryType] {
+        &Self::ETYPES
+    }
+
     pub fn iterator() -> Iter<'static, TimeLogEntryType> {
-        Self::ETYPES.iter()
+        Self::all().iter()
     }
+
+    pub fn is_leave(&self) -> bool {
+        match self {
+            TimeLogEntryType::Work | TimeLogEntryType::Overtime => false,
+            TimeLogEntryType::Holiday
+            | TimeLogEntryType::Sickness
+            | TimeLogEntryType::Vacation
+            | TimeLogEntryType::ParentalLeave => true,
+        }
+    }
+
+    pub fn counts_as_worked(&self) -> bool {
+        !self.is_leave()
+    }
+
+    // No work is expected on a day off, so Holiday time on one neither adds to the loggable
+    // time nor to the time logged towards it. See TimeLogDay::is_day_off.
+    pub fn is_day_off(&self) -> bool {
+        *self == TimeLogEntryType::Holiday
+    }
+
+    // Overtime is worked on top of the loggable time, so it never reduces the time left
+    pub fn counts_toward_target(&self) -> bool {
+        !self.is_day_off() && *self != TimeLogEntryType::Overtime
+    }
+}
+
+// Granularity of times logged with start/end
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimePrecision {
+    #[default]
+    Minutes,
+    Seconds,
 }
 
+impl TimePrecision {
+    pub fn truncate(&self, time: NaiveTime) -> NaiveTime {
+        match self {
+            TimePrecision::Minutes => NaiveTime::from_hms(time.hour(), time.minute(), 0),
+            TimePrecision::Seconds => {
+                NaiveTime::from_hms(time.hour(), time.minute(), time.second())
+            }
+        }
+    }
+}
+
+impl FromStr for TimePrecision {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<TimePrecision> {
+        match s {
+            "minutes" => Ok(TimePrecision::Minutes),
+            "seconds" => Ok(TimePrecision::Seconds),
+            _ => Err(TimeLogError::parse_error(format!(
+                "Can't parse: {} as TimePrecision",
+                s
+            ))),
+        }
+    }
+}
+
+// An unpaid lunch that is assumed on Work days longer than `above` without a logged break
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoLunch {
+    pub deduct: Duration,
+    pub above: Duration,
+}
+
+// Shorter gaps between Work entries, like a coffee, don't replace the lunch
+const MIN_BREAK_MINUTES: i64 = 15;
+
 impl fmt::Display for TimeLogEntryType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -115,6 +206,7 @@ impl FromStr for TimeLogEntryType {
             "Vacation" => Ok(TimeLogEntryType::Vacation),
             "Sickness" => Ok(TimeLogEntryType::Sickness),
             "Holiday" => Ok(TimeLogEntryType::Holiday),
+            "Overtime" => Ok(TimeLogEntryType::Overtime),
             _ => Err(TimeLogError::parse_error(format!(
                 "Can't parse: {} as TimeLogEntryType",
                 s
@@ -123,23 +215,28 @@ impl FromStr for TimeLogEntryType {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TimeLogEntry {
     start: Option<NaiveTime>,
     end: Option<NaiveTime>,
     entry_type: TimeLogEntryType,
     date: NaiveDate,
+    project: Option<String>,
+}
+
+fn since_midnight(time: NaiveTime) -> Duration {
+    time.signed_duration_since(NaiveTime::from_hms(0, 0, 0))
 }
 
 impl Ord for TimeLogEntry {
     fn cmp(&self, other: &TimeLogEntry) -> Ordering {
         match (
-            self.start,
-            self.end,
+            self.start_offset(),
+            self.end_offset(),
             self.entry_type,
             self.date,
-            other.start,
-            other.end,
+            other.start_offset(),
+            other.end_offset(),
             other.entry_type,
             other.date,
         ) {
@@ -165,6 +262,7 @@ impl TimeLogEntry {
             entry_type,
             start: Some(time),
             end: None,
+            project: None,
         }
     }
 
@@ -174,6 +272,7 @@ impl TimeLogEntry {
             entry_type,
             start: None,
             end: Some(time),
+            project: None,
         }
     }
 
@@ -202,6 +301,79 @@ impl TimeLogEntry {
     pub fn ty(&self) -> TimeLogEntryType {
         self.entry_type
     }
+
+    pub fn project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+
+    // An entry that was ended past midnight ends at 00:00:00, the midnight at the end of its
+    // day, so it isn't a second short
+    pub fn ends_at_midnight(&self) -> bool {
+        matches!((self.start, self.end), (Some(start), Some(end)) if end == NaiveTime::from_hms(0, 0, 0) && start > end)
+    }
+
+    fn start_offset(&self) -> Option<Duration> {
+        self.start.map(since_midnight)
+    }
+
+    fn end_offset(&self) -> Option<Duration> {
+        match self.ends_at_midnight() {
+            true => Some(Duration::days(1)),
+            false => self.end.map(since_midnight),
+        }
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        match (self.start_offset(), self.end_offset()) {
+            (Some(start), Some(end)) => Some(end - start),
+            _ => None,
+        }
+    }
+
+    // The part of the interval that is inside `window`, zero for entries without both times
+    pub fn duration_within(&self, window: (NaiveTime, NaiveTime)) -> Duration {
+        match (self.start_offset(), self.end_offset()) {
+            (Some(start), Some(end)) => {
+                let start = start.max(since_midnight(window.0));
+                let end = end.min(since_midnight(window.1));
+                match end > start {
+                    true => end - start,
+                    false => Duration::zero(),
+                }
+            }
+            _ => Duration::zero(),
+        }
+    }
+
+    pub fn shifted(&self, by: Duration) -> TimeLogResult<TimeLogEntry> {
+        let midnight = NaiveDateTime::new(self.date, NaiveTime::from_hms(0, 0, 0));
+        let shift = |t: Option<Duration>| t.map(|t| midnight + t + by);
+        let start = shift(self.start_offset());
+        let end = shift(self.end_offset());
+        let date = match (start, end) {
+            (Some(s), Some(e)) if s.date() != e.date() && e != s.date().succ().and_hms(0, 0, 0) => {
+                return Err(TimeLogError::InvalidInputError(format!(
+                    "Shifting {} would make it cross midnight",
+                    self
+                )))
+            }
+            (Some(s), _) => s.date(),
+            (None, Some(e)) => e.date(),
+            (None, None) => self.date,
+        };
+
+        Ok(TimeLogEntry {
+            date,
+            entry_type: self.entry_type,
+            start: start.map(|x| x.time()),
+            end: end.map(|x| x.time()),
+            project: self.project.clone(),
+        })
+    }
+}
+
+pub fn is_valid_project_name(s: &str) -> bool {
+    !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || c == '|' || c == '@')
 }
 
 fn try_get_naivetime(s: &str) -> Option<NaiveTime> {
@@ -213,6 +385,20 @@ fn try_get_naivetime(s: &str) -> Option<NaiveTime> {
 }
 
 const TIMELOGENTRY_NAIVEDATE_FORMAT_STRING: &str = "%Y/%m/%d %a";
+// Written with the alternate flag, `{:#}`, the weekday being redundant with the date
+const COMPACT_NAIVEDATE_FORMAT_STRING: &str = "%Y/%m/%d";
+
+fn parse_entry_date(s: &str) -> chrono::ParseResult<NaiveDate> {
+    NaiveDate::parse_from_str(s, TIMELOGENTRY_NAIVEDATE_FORMAT_STRING)
+        .or_else(|e| NaiveDate::parse_from_str(s, COMPACT_NAIVEDATE_FORMAT_STRING).map_err(|_| e))
+}
+
+fn date_format(f: &Formatter) -> &'static str {
+    match f.alternate() {
+        true => COMPACT_NAIVEDATE_FORMAT_STRING,
+        false => TIMELOGENTRY_NAIVEDATE_FORMAT_STRING,
+    }
+}
 
 impl FromStr for TimeLogEntry {
     type Err = TimeLogError;
@@ -220,12 +406,11 @@ impl FromStr for TimeLogEntry {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut delim_split = s.split('|');
 
-        let date: NaiveDate = NaiveDate::parse_from_str(
+        let date: NaiveDate = parse_entry_date(
             delim_split
                 .next()
                 .ok_or_else(|| TimeLogError::parse_error(format!("Can't read date from: {}", s)))?
                 .trim(),
-            TIMELOGENTRY_NAIVEDATE_FORMAT_STRING,
         )?;
 
         let mut space_split = delim_split
@@ -234,11 +419,20 @@ impl FromStr for TimeLogEntry {
             .trim()
             .split(' ');
 
-        let entry_type: TimeLogEntryType = space_split
+        let type_token = space_split
             .next()
             .ok_or_else(|| TimeLogError::parse_error(format!("Can't read type from: {}", s)))?
-            .trim()
-            .parse()?;
+            .trim();
+        let (entry_type, project) = match type_token.split_once('@') {
+            Some((_, project)) if !is_valid_project_name(project) => {
+                return Err(TimeLogError::parse_error(format!(
+                    "Invalid project name in: {}",
+                    s
+                )))
+            }
+            Some((ty, project)) => (ty.parse()?, Some(project.to_string())),
+            None => (type_token.parse()?, None),
+        };
 
         let start = try_get_naivetime(
             space_split
@@ -252,12 +446,23 @@ impl FromStr for TimeLogEntry {
                 .ok_or_else(|| TimeLogError::parse_error(format!("Can't read end from: {}", s)))?
                 .trim(),
         );
-        Ok(TimeLogEntry {
+        let entry = TimeLogEntry {
             date,
             entry_type,
             start,
             end,
-        })
+            project,
+        };
+        // Entries can't cross midnight, so a reversed interval is always an error. An end at
+        // 00:00:00 is the midnight at the end of the day, see ends_at_midnight.
+        if entry.duration().is_some_and(|d| d < Duration::zero()) {
+            return Err(TimeLogError::parse_error(format!(
+                "End is before start in: {}",
+                s
+            )));
+        }
+
+        Ok(entry)
     }
 }
 
@@ -272,16 +477,68 @@ impl Display for TimeLogEntry {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(
             f,
-            "{} | {} {} {}",
-            self.date.format(TIMELOGENTRY_NAIVEDATE_FORMAT_STRING),
-            self.entry_type,
+            "{} | {}",
+            self.date.format(date_format(f)),
+            self.entry_type
+        )?;
+        if let Some(ref project) = self.project {
+            write!(f, "@{}", project)?;
+        }
+        write!(
+            f,
+            " {} {}",
             opt_naivetime_to_str(self.start),
             opt_naivetime_to_str(self.end)
         )
     }
 }
 
-#[derive(Debug, PartialEq)]
+const FLEX_RESET_MARKER: &str = "FLEX RESET";
+
+// Flex time is only accumulated from the latest reset onwards
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FlexReset {
+    date: NaiveDate,
+}
+
+impl FlexReset {
+    pub fn new(date: NaiveDate) -> Self {
+        FlexReset { date }
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+impl FromStr for FlexReset {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<FlexReset> {
+        match s.split_once('|') {
+            Some((date, marker)) if marker.trim() == FLEX_RESET_MARKER => Ok(FlexReset {
+                date: parse_entry_date(date.trim())?,
+            }),
+            _ => Err(TimeLogError::parse_error(format!(
+                "Not a flex reset marker: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for FlexReset {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} | {}",
+            self.date.format(date_format(f)),
+            FLEX_RESET_MARKER
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TimeLogDay {
     date: NaiveDate,
     entries: Vec<TimeLogEntry>,
@@ -317,9 +574,9 @@ macro_rules! gen_set {
             let out = match out {
                 None => {
                     self.entries.push($ctor(self.date, entry_type, time));
-                    *self.entries.last().expect("Just added this?")
+                    self.entries.last().expect("Just added this?").clone()
                 }
-                Some(x) => *x,
+                Some(x) => x.clone(),
             };
             self.entries.sort();
             out
@@ -337,25 +594,44 @@ fn is_workday(date: NaiveDate) -> bool {
 
 impl TimeLogDay {
     fn validate_ordering(&self) -> bool {
-        for i in 0..self.entries.len() {
-            for j in (i + 1)..self.entries.len() {
-                let s_i = self.entries[i].start;
-                let s_j = self.entries[j].start;
-                let e_i = self.entries[i].end;
-                let e_j = self.entries[j].end;
-                if s_i.is_some() && s_j.is_some() {
-                    debug_assert!(self.entries[i].start.unwrap() < self.entries[j].start.unwrap());
-                }
-                if e_i.is_some() && e_j.is_some() {
-                    debug_assert!(self.entries[i].end.unwrap() < self.entries[j].end.unwrap());
+        self.validate().is_ok()
+    }
+
+    // Checks that every interval ends after it starts and that no two intervals overlap
+    pub fn validate(&self) -> TimeLogResult<()> {
+        for e in &self.entries {
+            if let (Some(start), Some(end)) = (e.start_offset(), e.end_offset()) {
+                if start >= end {
+                    return Err(TimeLogError::inv_inp(
+                        format!("Entry does not end after it starts: {}", e).as_str(),
+                    ));
                 }
+            }
+        }
 
-                if e_i.is_some() && s_j.is_some() {
-                    debug_assert!(self.entries[i].end.unwrap() <= self.entries[j].start.unwrap());
+        for i in 0..self.entries.len() {
+            for j in (i + 1)..self.entries.len() {
+                let (a, b) = (&self.entries[i], &self.entries[j]);
+                let ordered = match (
+                    a.start_offset(),
+                    b.start_offset(),
+                    a.end_offset(),
+                    b.end_offset(),
+                ) {
+                    (Some(s_a), Some(s_b), _, _) if s_a >= s_b => false,
+                    (_, _, Some(e_a), Some(e_b)) if e_a >= e_b => false,
+                    (_, Some(s_b), Some(e_a), _) => e_a <= s_b,
+                    _ => true,
+                };
+                if !ordered {
+                    return Err(TimeLogError::inv_inp(
+                        format!("Overlapping entries:\n{}\n{}", a, b).as_str(),
+                    ));
                 }
             }
         }
-        true
+
+        Ok(())
     }
 
     pub fn add_entry(&mut self, e: TimeLogEntry) {
@@ -363,6 +639,23 @@ impl TimeLogDay {
         self.entries.sort();
     }
 
+    // Unlike add_entry, an interval that overlaps one of the same type is rejected
+    pub fn try_add_entry(&mut self, e: TimeLogEntry) -> TimeLogResult<()> {
+        if let (Some(start), Some(end)) = (e.start_offset(), e.end_offset()) {
+            let overlapping = self
+                .filtered_by_type(e.entry_type)
+                .find(|o| matches!((o.start_offset(), o.end_offset()), (Some(s), Some(en)) if s < end && start < en));
+            if let Some(o) = overlapping {
+                return Err(TimeLogError::inv_inp(
+                    format!("Overlapping entries:\n{}\n{}", o, e).as_str(),
+                ));
+            }
+        }
+
+        self.add_entry(e);
+        Ok(())
+    }
+
     pub fn empty(date: NaiveDate) -> TimeLogDay {
         TimeLogDay {
             date,
@@ -376,10 +669,136 @@ impl TimeLogDay {
             entry_type,
             start: None,
             end: None,
+            project: None,
+        }];
+        TimeLogDay { date, entries }
+    }
+
+    pub fn full_with_interval(
+        date: NaiveDate,
+        entry_type: TimeLogEntryType,
+        start: NaiveTime,
+        end: NaiveTime,
+    ) -> Self {
+        let entries = vec![TimeLogEntry {
+            date,
+            entry_type,
+            start: Some(start),
+            end: Some(end),
+            project: None,
         }];
         TimeLogDay { date, entries }
     }
 
+    pub fn full_work_with_defaults(date: NaiveDate, start: NaiveTime, end: NaiveTime) -> Self {
+        TimeLogDay::full_with_interval(date, TimeLogEntryType::Work, start, end)
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    pub fn entries(&self) -> &[TimeLogEntry] {
+        &self.entries
+    }
+
+    pub fn remove_entry(&mut self, index: usize) -> Option<TimeLogEntry> {
+        if index < self.entries.len() {
+            Some(self.entries.remove(index))
+        } else {
+            None
+        }
+    }
+
+    // Returns the number of removed entries
+    pub fn remove_entries_where<P>(&mut self, pred: P) -> usize
+    where
+        P: Fn(&TimeLogEntry) -> bool,
+    {
+        let before = self.entries.len();
+        self.entries.retain(|e| !pred(e));
+        before - self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Joins complete entries of the same type and project where one ends exactly when the
+    // next starts. Returns the number of entries removed.
+    pub fn merge_adjacent(&mut self) -> usize {
+        let before = self.entries.len();
+        let mut merged: Vec<TimeLogEntry> = Vec::with_capacity(before);
+        for e in self.entries.drain(..) {
+            if let Some(prev) = merged.last_mut() {
+                if prev.entry_type == e.entry_type
+                    && prev.project == e.project
+                    && prev.start.is_some()
+                    && prev.end.is_some()
+                    && prev.end_offset() == e.start_offset()
+                    && e.end.is_some()
+                {
+                    prev.end = e.end;
+                    continue;
+                }
+            }
+            merged.push(e);
+        }
+        self.entries = merged;
+
+        before - self.entries.len()
+    }
+
+    // Equal as multisets of entries, since entries without times have no well defined order
+    #[cfg(test)]
+    pub fn eq_ignoring_order(&self, other: &TimeLogDay) -> bool {
+        let mut rest: Vec<&TimeLogEntry> = other.entries.iter().collect();
+        self.date == other.date
+            && self.entries.len() == rest.len()
+            && self
+                .entries
+                .iter()
+                .all(|e| match rest.iter().position(|o| *o == e) {
+                    Some(i) => {
+                        rest.swap_remove(i);
+                        true
+                    }
+                    None => false,
+                })
+    }
+
+    // The mutators keep the entries sorted but a parsed day is stored as written, so
+    // this only clones when something is out of order
+    fn ensure_sorted(&self) -> Cow<'_, [TimeLogEntry]> {
+        if self.entries.is_sorted() {
+            Cow::Borrowed(&self.entries)
+        } else {
+            let mut entries = self.entries.clone();
+            entries.sort();
+            Cow::Owned(entries)
+        }
+    }
+
+    pub fn fmt_indexed(&self, index: usize) -> String {
+        format!("[{}] {}", index, self.entries[index])
+    }
+
+    pub fn filtered_by_type(&self, ty: TimeLogEntryType) -> impl Iterator<Item = &TimeLogEntry> {
+        self.entries.iter().filter(move |e| e.entry_type == ty)
+    }
+
+    pub fn rename_project(&mut self, old: &str, new: &str) -> usize {
+        let mut count = 0;
+        for e in self.entries.iter_mut() {
+            if e.project.as_deref() == Some(old) {
+                e.project = Some(new.to_string());
+                count += 1;
+            }
+        }
+
+        count
+    }
+
     gen_set!(set_end, end, set_end, TimeLogEntry::from_end);
     gen_set!(set_start, start, set_start, TimeLogEntry::from_start);
 
@@ -387,8 +806,9 @@ impl TimeLogDay {
         &self,
         with: Option<NaiveTime>,
         etype: TimeLogEntryType,
+        full_day: Duration,
     ) -> TimeLogResult<Duration> {
-        let mut dur = self.logged_time(etype);
+        let mut dur = self.logged_time(etype, full_day);
         if with.is_none() {
             return Ok(dur);
         }
@@ -419,49 +839,138 @@ impl TimeLogDay {
         Ok(dur)
     }
 
-    pub fn loggable_time(&self, _etype: TimeLogEntryType) -> Duration {
-        if is_workday(self.date) {
-            Duration::hours(8)
+    // Overtime counts towards the maximum as well, it is still time worked
+    pub fn exceeds(&self, max: Duration, with: Option<NaiveTime>) -> bool {
+        TimeLogEntryType::all()
+            .iter()
+            .filter(|ty| ty.counts_as_worked())
+            .try_fold(Duration::zero(), |worked, ty| {
+                // Only leave counts as a full day, so the length of one doesn't matter here
+                self.time_logged_with(with, *ty, Duration::zero())
+                    .map(|dur| worked + dur)
+            })
+            .map(|worked| worked > max)
+            .unwrap_or(false)
+    }
+
+    // A day with only Holiday entries, see loggable_time
+    pub fn is_day_off(&self) -> bool {
+        !self.entries.is_empty() && self.entries.iter().all(|e| e.entry_type.is_day_off())
+    }
+
+    // `full_day` is what a regular workday is expected to have logged
+    pub fn loggable_time(&self, full_day: Duration) -> Duration {
+        if is_workday(self.date) && !self.is_day_off() {
+            full_day
         } else {
             Duration::hours(0)
         }
     }
 
-    pub fn logged_time(&self, etype: TimeLogEntryType) -> Duration {
+    // `full_day` is what leave without times counts as, see loggable_time
+    fn logged_time_where<P>(&self, full_day: Duration, pred: P) -> Duration
+    where
+        P: Fn(&TimeLogEntry) -> bool,
+    {
         debug_assert!(self.validate_ordering());
         let mut sum = Duration::seconds(0);
-        for e in &self.entries {
-            if e.entry_type == etype {
-                if etype == TimeLogEntryType::Work {
-                    if let (Some(start), Some(end)) = (e.start, e.end) {
-                        debug_assert!(e.start < e.end);
-                        sum = sum + end.signed_duration_since(start);
-                    }
-                } else {
-                    sum = sum + Duration::hours(8);
+        for e in self.entries.iter().filter(|e| pred(e)) {
+            if e.entry_type.counts_as_worked() {
+                if let Some(dur) = e.duration() {
+                    debug_assert!(e.start_offset() < e.end_offset());
+                    sum = sum + dur;
                 }
+            } else {
+                // Leave without times is a full day
+                sum = sum + e.duration().unwrap_or(full_day);
             }
         }
 
         sum
     }
 
-    pub fn has_unfinished_entries(&self) -> bool {
-        self.entries.iter().any(|&e| {
-            e.entry_type == TimeLogEntryType::Work && (e.start.is_none() || e.end.is_none())
-                || (e.start.is_some() && e.end.is_none() || e.start.is_none() && e.end.is_some())
+    pub fn logged_time(&self, etype: TimeLogEntryType, full_day: Duration) -> Duration {
+        self.logged_time_where(full_day, |e| e.entry_type == etype)
+    }
+
+    // A break is a gap of at least MIN_BREAK_MINUTES between two Work entries, so a day with
+    // a single Work entry, or with entries only a few minutes apart, has none
+    fn has_break(&self) -> bool {
+        let min_break = Duration::minutes(MIN_BREAK_MINUTES);
+        self.worked_intervals()
+            .windows(2)
+            .any(|w| w[1].0.signed_duration_since(w[0].1) >= min_break)
+    }
+
+    pub fn apply_auto_lunch(
+        &self,
+        lunch: Option<AutoLunch>,
+        etype: TimeLogEntryType,
+        logged: Duration,
+    ) -> Duration {
+        match lunch {
+            Some(l) if etype == TimeLogEntryType::Work && logged > l.above && !self.has_break() => {
+                logged - l.deduct
+            }
+            _ => logged,
+        }
+    }
+
+    pub fn logged_time_for_project(
+        &self,
+        etype: TimeLogEntryType,
+        project: Option<&str>,
+        full_day: Duration,
+    ) -> Duration {
+        self.logged_time_where(full_day, |e| {
+            e.entry_type == etype && e.project() == project
         })
     }
+
+    // The Work entries that have both a start and an end
+    pub fn worked_intervals(&self) -> Vec<(NaiveTime, NaiveTime)> {
+        self.filtered_by_type(TimeLogEntryType::Work)
+            .filter_map(|e| e.start.zip(e.end))
+            .collect()
+    }
+
+    // Start of the Work entry that has been started but not yet ended
+    pub fn open_entry_start(&self) -> Option<NaiveTime> {
+        self.filtered_by_type(TimeLogEntryType::Work)
+            .find(|e| e.start.is_some() && e.end.is_none())
+            .and_then(|e| e.start)
+    }
+
+    // Indices, as in `view --index`, of the entries that are missing a start or an end
+    pub fn open_entries(&self) -> Vec<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| {
+                e.entry_type.counts_as_worked() && (e.start.is_none() || e.end.is_none())
+                    || (e.start.is_some() && e.end.is_none()
+                        || e.start.is_none() && e.end.is_some())
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn has_unfinished_entries(&self) -> bool {
+        !self.open_entries().is_empty()
+    }
 }
 
 impl Display for TimeLogDay {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // An empty day is formatted as an empty string
         let mut s: String = String::new();
-        for (i, entry) in self.entries.iter().enumerate() {
-            if i != self.entries.len() - 1 {
-                s.push_str(format!("{}\n", entry).as_str());
-            } else {
-                s.push_str(format!("{}", entry).as_str());
+        for (i, entry) in self.ensure_sorted().iter().enumerate() {
+            if i != 0 {
+                s.push('\n');
+            }
+            match f.alternate() {
+                true => s.push_str(format!("{:#}", entry).as_str()),
+                false => s.push_str(format!("{}", entry).as_str()),
             }
         }
         write!(f, "{}", s.as_str())
@@ -476,13 +985,22 @@ impl FromStr for TimeLogDay {
         for l in s.lines() {
             v.push(l.trim().parse()?);
         }
-        debug_assert!(!v.is_empty());
-        debug_assert!(v.iter().all(|x| x.date == v[0].date));
+        let date = match v.first() {
+            Some(e) => e.date,
+            None => {
+                return Err(TimeLogError::parse_error(String::from(
+                    "No entries for day",
+                )))
+            }
+        };
+        if let Some(e) = v.iter().find(|e| e.date != date) {
+            return Err(TimeLogError::parse_error(format!(
+                "Entry for {} in day {}",
+                e.date, date
+            )));
+        }
 
-        Ok(TimeLogDay {
-            date: v[0].date,
-            entries: v,
-        })
+        Ok(TimeLogDay { date, entries: v })
     }
 }
 
@@ -491,6 +1009,59 @@ mod tests {
     use super::*;
     use chrono::Duration;
     use chrono::NaiveTime;
+    #[test]
+    fn timelogerror_source() {
+        let io_err = TimeLogError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        let source = io_err.source().expect("IOError should have a source");
+        assert_eq!(source.to_string(), "gone");
+
+        let time_err = NaiveDate::parse_from_str("nope", "%Y/%m/%d").unwrap_err();
+        assert!(TimeLogError::from(time_err).source().is_some());
+
+        assert!(TimeLogError::inv_inp("bad").source().is_none());
+        assert!(TimeLogError::ParseError(String::from("bad"))
+            .source()
+            .is_none());
+    }
+
+    #[test]
+    fn timelogentrytype_all() {
+        // Fails to compile when a variant is added, as a reminder to add it to ETYPES
+        let n_variants = |ty: TimeLogEntryType| match ty {
+            TimeLogEntryType::Work
+            | TimeLogEntryType::Holiday
+            | TimeLogEntryType::Sickness
+            | TimeLogEntryType::Vacation
+            | TimeLogEntryType::ParentalLeave
+            | TimeLogEntryType::Overtime => 6,
+        };
+        let all = TimeLogEntryType::all();
+        assert_eq!(all.len(), n_variants(TimeLogEntryType::Work));
+        assert!(all
+            .iter()
+            .all(|ty| all.iter().filter(|x| *x == ty).count() == 1));
+        assert!(TimeLogEntryType::iterator().eq(all.iter()));
+    }
+
+    #[test]
+    fn timelogentrytype_classification() {
+        for ty in TimeLogEntryType::iterator() {
+            let expect_leave = *ty != TimeLogEntryType::Work && *ty != TimeLogEntryType::Overtime;
+            assert_eq!(ty.is_leave(), expect_leave, "{}", ty);
+            assert_eq!(ty.counts_as_worked(), !expect_leave, "{}", ty);
+            assert_eq!(ty.to_string().parse::<TimeLogEntryType>().unwrap(), *ty);
+        }
+        assert!(!TimeLogEntryType::Overtime.counts_toward_target());
+        assert!(!TimeLogEntryType::Holiday.counts_toward_target());
+        assert!(TimeLogEntryType::Sickness.counts_toward_target());
+        assert_eq!(
+            TimeLogEntryType::iterator()
+                .filter(|t| t.is_leave())
+                .count(),
+            4
+        );
+    }
+
     #[test]
     fn timelogentry_basic_mutators() {
         let tdy = Local::today().naive_local();
@@ -511,6 +1082,60 @@ mod tests {
         assert_eq!(entry3.end, Some(end_time));
     }
 
+    #[test]
+    fn timelogentry_shifted() {
+        let entry: TimeLogEntry = "2017/12/18 Mon | Work 07:30:00 16:00:00".parse().unwrap();
+        assert_eq!(
+            entry.shifted(-Duration::hours(2)).unwrap().to_string(),
+            "2017/12/18 Mon | Work 05:30:00 14:00:00"
+        );
+        assert_eq!(
+            entry.shifted(Duration::hours(9)).unwrap_err(),
+            TimeLogError::inv_inp("")
+        );
+
+        let open: TimeLogEntry = "2017/12/18 Mon | Work 23:00:00 UNDEF".parse().unwrap();
+        assert_eq!(
+            open.shifted(Duration::hours(2)).unwrap().to_string(),
+            "2017/12/19 Tue | Work 01:00:00 UNDEF"
+        );
+
+        let leave: TimeLogEntry = "2017/12/18 Mon | Vacation UNDEF UNDEF".parse().unwrap();
+        assert_eq!(leave.shifted(-Duration::hours(2)).unwrap(), leave);
+    }
+
+    #[test]
+    fn timelogentry_ends_at_midnight() {
+        let entry: TimeLogEntry = "2017/12/18 Mon | Work 22:00:00 00:00:00".parse().unwrap();
+        assert!(entry.ends_at_midnight());
+        assert_eq!(entry.duration(), Some(Duration::hours(2)));
+        assert_eq!(
+            entry.shifted(-Duration::hours(1)).unwrap().to_string(),
+            "2017/12/18 Mon | Work 21:00:00 23:00:00"
+        );
+        assert!(entry.shifted(Duration::hours(1)).is_err());
+        let earlier: TimeLogEntry = "2017/12/18 Mon | Work 20:00:00 21:00:00".parse().unwrap();
+        assert_eq!(
+            earlier.shifted(Duration::hours(3)).unwrap(),
+            "2017/12/18 Mon | Work 23:00:00 00:00:00".parse().unwrap()
+        );
+
+        let day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                               2017/12/18 Mon | Work 22:00:00 00:00:00"
+            .parse()
+            .unwrap();
+        assert!(day.validate().is_ok());
+        assert_eq!(
+            day.logged_time(TimeLogEntryType::Work, Duration::hours(8)),
+            Duration::hours(6)
+        );
+        assert_eq!(day.entries()[1], entry);
+        assert!(day.entries()[0] < entry);
+
+        let zero: TimeLogEntry = "2017/12/18 Mon | Work 00:00:00 00:00:00".parse().unwrap();
+        assert!(!zero.ends_at_midnight());
+    }
+
     #[test]
     fn timelogentry_from_str() {
         let all_undef = "2017/12/22 Fri | Work UNDEF UNDEF";
@@ -546,6 +1171,100 @@ mod tests {
         assert_eq!(all_undef_s.date, NaiveDate::from_ymd(2017, 12, 22));
     }
 
+    #[test]
+    fn timelogentry_duration_within() {
+        let entry: TimeLogEntry = "2017/12/22 Fri | Work 08:00:00 12:00:00".parse().unwrap();
+        let hms = |h, m| NaiveTime::from_hms(h, m, 0);
+
+        assert_eq!(
+            entry.duration_within((hms(7, 0), hms(13, 0))),
+            Duration::hours(4)
+        );
+        assert_eq!(
+            entry.duration_within((hms(10, 30), hms(17, 0))),
+            Duration::minutes(90)
+        );
+        assert_eq!(
+            entry.duration_within((hms(6, 0), hms(9, 0))),
+            Duration::hours(1)
+        );
+        assert_eq!(
+            entry.duration_within((hms(12, 0), hms(17, 0))),
+            Duration::zero()
+        );
+        assert_eq!(
+            entry.duration_within((hms(18, 0), hms(20, 0))),
+            Duration::zero()
+        );
+
+        let open: TimeLogEntry = "2017/12/22 Fri | Work 08:00:00 UNDEF".parse().unwrap();
+        assert_eq!(
+            open.duration_within((hms(7, 0), hms(13, 0))),
+            Duration::zero()
+        );
+    }
+
+    #[test]
+    fn timelogentry_reversed_interval() {
+        let reversed = "2017/12/22 Fri | Work 12:00:00 08:00:00".parse::<TimeLogEntry>();
+        assert!(matches!(reversed, Err(TimeLogError::ParseError(_))));
+        assert!("2017/12/22 Fri | Work 12:00:00 08:00:00"
+            .parse::<TimeLogDay>()
+            .is_err());
+    }
+
+    #[test]
+    fn timelogentry_project() {
+        let tagged: TimeLogEntry = "2017/12/22 Fri | Work@acme 07:31:00 12:00:00"
+            .parse()
+            .unwrap();
+        assert_eq!(tagged.entry_type, TimeLogEntryType::Work);
+        assert_eq!(tagged.project(), Some("acme"));
+        assert_eq!(tagged.start, Some(NaiveTime::from_hms(7, 31, 0)));
+        assert_eq!(
+            tagged.to_string(),
+            "2017/12/22 Fri | Work@acme 07:31:00 12:00:00"
+        );
+
+        let untagged: TimeLogEntry = "2017/12/22 Fri | Work 07:31:00 12:00:00".parse().unwrap();
+        assert_eq!(untagged.project(), None);
+
+        assert!("2017/12/22 Fri | Work@ 07:31:00 12:00:00"
+            .parse::<TimeLogEntry>()
+            .is_err());
+        assert!("2017/12/22 Fri | Work@ac@me 07:31:00 12:00:00"
+            .parse::<TimeLogEntry>()
+            .is_err());
+        assert!("2017/12/22 Fri | Nap@acme 07:31:00 12:00:00"
+            .parse::<TimeLogEntry>()
+            .is_err());
+    }
+
+    #[test]
+    fn timelogday_logged_time_for_project() {
+        let s = "2017/12/18 Mon | Work@acme 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work@initech 13:00:00 15:30:00\n\
+                 2017/12/18 Mon | Work 15:30:00 16:00:00";
+        let day: TimeLogDay = s.parse().unwrap();
+        let work = TimeLogEntryType::Work;
+        assert_eq!(
+            day.logged_time_for_project(work, Some("acme"), Duration::hours(8)),
+            Duration::hours(4)
+        );
+        assert_eq!(
+            day.logged_time_for_project(work, Some("initech"), Duration::hours(8)),
+            Duration::minutes(150)
+        );
+        assert_eq!(
+            day.logged_time_for_project(work, None, Duration::hours(8)),
+            Duration::minutes(30)
+        );
+        assert_eq!(
+            day.logged_time(work, Duration::hours(8)),
+            Duration::minutes(7 * 60)
+        );
+    }
+
     #[test]
     fn timelogentry_consistent_serialiation() {
         let all_undef = "2017/12/22 Fri | Work UNDEF UNDEF";
@@ -580,6 +1299,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn flexreset_serialization() {
+        let s = "2017/12/18 Mon | FLEX RESET";
+        let reset: FlexReset = s.parse().unwrap();
+        assert_eq!(reset.date(), NaiveDate::from_ymd(2017, 12, 18));
+        assert_eq!(reset.to_string(), s);
+
+        assert!("2017/12/18 Mon | Work UNDEF UNDEF"
+            .parse::<FlexReset>()
+            .is_err());
+        let compact: FlexReset = "2017/12/18 | FLEX RESET".parse().unwrap();
+        assert_eq!(compact, reset);
+        assert_eq!(format!("{:#}", compact), "2017/12/18 | FLEX RESET");
+    }
+
     #[test]
     fn timelogday_mutators() {
         let mut mon = TimeLogDay::empty(NaiveDate::from_ymd(2017, 11, 20));
@@ -674,7 +1408,13 @@ mod tests {
         assert_eq!(day.entries[4].date, NaiveDate::from_ymd(2017, 12, 18));
         assert_eq!(day.date, NaiveDate::from_ymd(2017, 12, 18));
 
-        assert_eq!(day.to_string(), s);
+        // Entries without times are written in entry type order
+        let sorted = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            all_undef, start, all_undef_s, all_undef_vac, all_undef_pl
+        );
+        assert_eq!(day.to_string(), sorted);
+        assert_eq!(sorted.parse::<TimeLogDay>().unwrap().to_string(), sorted);
     }
 
     #[test]
@@ -716,6 +1456,21 @@ mod tests {
         assert_eq!(day.entries[2].date, NaiveDate::from_ymd(2017, 12, 18));
     }
 
+    #[test]
+    fn timelogday_set_returns_stored_entry() {
+        let mut day: TimeLogDay = "2017/12/18 Mon | Work 07:31:00 UNDEF".parse().unwrap();
+
+        let ended = day.set_end(NaiveTime::from_hms(12, 0, 0), TimeLogEntryType::Work);
+        assert_eq!(ended.start(), Some(NaiveTime::from_hms(7, 31, 0)));
+        assert_eq!(ended.end(), Some(NaiveTime::from_hms(12, 0, 0)));
+        assert_eq!(ended, day.entries[0]);
+
+        let started = day.set_start(NaiveTime::from_hms(13, 0, 0), TimeLogEntryType::Work);
+        assert_eq!(started.start(), Some(NaiveTime::from_hms(13, 0, 0)));
+        assert_eq!(started.end(), None);
+        assert_eq!(started, day.entries[1]);
+    }
+
     #[test]
     fn timelogday_diff_entry_types() {
         let entries = vec![
@@ -757,6 +1512,274 @@ mod tests {
         assert_eq!(day.entries[2].date, NaiveDate::from_ymd(2017, 12, 18));
     }
 
+    #[test]
+    fn timelogday_validate() {
+        let ok = "2017/12/18 Mon | Work 08:00:00 12:00:00\n2017/12/18 Mon | Work 12:00:00 UNDEF"
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert_eq!(ok.validate(), Ok(()));
+
+        let overlapping =
+            "2017/12/18 Mon | Work 08:00:00 12:00:00\n2017/12/18 Mon | Work 11:00:00 16:00:00"
+                .parse::<TimeLogDay>()
+                .unwrap();
+        assert!(matches!(
+            overlapping.validate(),
+            Err(TimeLogError::InvalidInputError(m)) if m.starts_with("Overlapping entries")
+        ));
+
+        // Can't be parsed, but can still be constructed
+        let backwards = TimeLogDay::full_with_interval(
+            NaiveDate::from_ymd(2017, 12, 18),
+            TimeLogEntryType::Work,
+            NaiveTime::from_hms(16, 0, 0),
+            NaiveTime::from_hms(8, 0, 0),
+        );
+        assert!(matches!(
+            backwards.validate(),
+            Err(TimeLogError::InvalidInputError(m))
+                if m.starts_with("Entry does not end after it starts")
+        ));
+    }
+
+    #[test]
+    fn timelogday_loggable_time_day_off() {
+        let holiday = "2017/12/25 Mon | Holiday UNDEF UNDEF"
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert_eq!(
+            holiday.loggable_time(Duration::hours(8)),
+            Duration::hours(0)
+        );
+
+        let vacation = "2017/12/25 Mon | Vacation UNDEF UNDEF"
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert_eq!(
+            vacation.loggable_time(Duration::minutes(7 * 60 + 30)),
+            Duration::minutes(7 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn timelogday_exceeds() {
+        let tld =
+            "2017/12/18 Mon | Work 07:00:00 12:00:00\n2017/12/18 Mon | Work 12:30:00 18:00:00"
+                .parse::<TimeLogDay>()
+                .unwrap();
+        assert!(tld.exceeds(Duration::hours(10), None));
+        assert!(!tld.exceeds(Duration::minutes(10 * 60 + 30), None));
+
+        let with_overtime =
+            "2017/12/18 Mon | Work 07:00:00 12:00:00\n2017/12/18 Mon | Overtime 12:30:00 18:00:00"
+                .parse::<TimeLogDay>()
+                .unwrap();
+        assert!(with_overtime.exceeds(Duration::hours(10), None));
+        assert!(!with_overtime.exceeds(Duration::minutes(10 * 60 + 30), None));
+
+        let long_overtime =
+            "2017/12/18 Mon | Work 08:00:00 16:00:00\n2017/12/18 Mon | Overtime 16:00:00 19:00:00"
+                .parse::<TimeLogDay>()
+                .unwrap();
+        assert!(long_overtime.exceeds(Duration::minutes(10 * 60 + 30), None));
+
+        let open = "2017/12/18 Mon | Work 07:00:00 UNDEF"
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert!(!open.exceeds(Duration::hours(10), None));
+        assert!(open.exceeds(Duration::hours(10), Some(NaiveTime::from_hms(17, 30, 0))));
+    }
+
+    #[test]
+    fn timelogday_display_empty() {
+        let tld = TimeLogDay::empty(NaiveDate::from_ymd(2017, 12, 18));
+        assert_eq!(tld.to_string(), "");
+    }
+
+    #[test]
+    fn timelogday_try_add_entry() {
+        let mut day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00".parse().unwrap();
+        let entry = |s: &str| s.parse::<TimeLogEntry>().unwrap();
+
+        assert!(day
+            .try_add_entry(entry("2017/12/18 Mon | Work 11:00:00 13:00:00"))
+            .is_err());
+        assert!(day
+            .try_add_entry(entry("2017/12/18 Mon | Work 07:00:00 16:00:00"))
+            .is_err());
+        assert_eq!(day.entries().len(), 1);
+
+        day.try_add_entry(entry("2017/12/18 Mon | Work 12:00:00 13:00:00"))
+            .unwrap();
+        day.try_add_entry(entry("2017/12/18 Mon | Vacation 09:00:00 10:00:00"))
+            .unwrap();
+        day.try_add_entry(entry("2017/12/18 Mon | Work 14:00:00 UNDEF"))
+            .unwrap();
+        assert_eq!(day.entries().len(), 4);
+    }
+
+    #[test]
+    fn timelogday_eq_ignoring_order() {
+        let a: TimeLogDay = "2017/12/18 Mon | Vacation UNDEF UNDEF\n\
+                             2017/12/18 Mon | Work@acme 08:00:00 12:00:00\n\
+                             2017/12/18 Mon | Work 08:00:00 12:00:00"
+            .parse()
+            .unwrap();
+        let b: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                             2017/12/18 Mon | Vacation UNDEF UNDEF\n\
+                             2017/12/18 Mon | Work@acme 08:00:00 12:00:00"
+            .parse()
+            .unwrap();
+        assert_ne!(a, b);
+        assert!(a.eq_ignoring_order(&b));
+        assert!(b.eq_ignoring_order(&a));
+
+        let c: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                             2017/12/18 Mon | Vacation UNDEF UNDEF\n\
+                             2017/12/18 Mon | Work 08:00:00 12:00:00"
+            .parse()
+            .unwrap();
+        assert!(!a.eq_ignoring_order(&c));
+        assert!(!c.eq_ignoring_order(&a));
+        assert!(!a.eq_ignoring_order(&TimeLogDay::empty(a.date())));
+    }
+
+    #[test]
+    fn timelogday_display_sorted() {
+        let s = "2017/12/18 Mon | Work 12:00:00 16:00:00\n\
+                 2017/12/18 Mon | Work 08:00:00 11:00:00";
+        let day: TimeLogDay = s.parse().unwrap();
+        assert_eq!(
+            day.to_string(),
+            "2017/12/18 Mon | Work 08:00:00 11:00:00\n\
+             2017/12/18 Mon | Work 12:00:00 16:00:00"
+        );
+        assert_eq!(
+            day.entries()[0].to_string(),
+            "2017/12/18 Mon | Work 12:00:00 16:00:00"
+        );
+    }
+
+    #[test]
+    fn timelogday_filtered_by_type() {
+        let s = "2017/12/18 Mon | Work 06:31:00 07:00:00\n\
+                 2017/12/18 Mon | Vacation UNDEF UNDEF\n\
+                 2017/12/18 Mon | Work 07:31:00 UNDEF";
+        let day: TimeLogDay = s.parse().unwrap();
+
+        let vacation: Vec<String> = day
+            .filtered_by_type(TimeLogEntryType::Vacation)
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(vacation, vec!["2017/12/18 Mon | Vacation UNDEF UNDEF"]);
+
+        assert_eq!(day.filtered_by_type(TimeLogEntryType::Work).count(), 2);
+        assert_eq!(day.filtered_by_type(TimeLogEntryType::Sickness).count(), 0);
+    }
+
+    #[test]
+    fn timelogday_fmt_indexed() {
+        let s = "2017/12/18 Mon | Work 06:31:00 07:00:00\n\
+                 2017/12/18 Mon | Work 07:31:00 UNDEF\n\
+                 2017/12/18 Mon | Vacation UNDEF UNDEF";
+        let day: TimeLogDay = s.parse().unwrap();
+
+        assert_eq!(
+            day.fmt_indexed(1),
+            "[1] 2017/12/18 Mon | Work 07:31:00 UNDEF"
+        );
+        for (i, entry) in day.entries().iter().enumerate() {
+            assert_eq!(day.fmt_indexed(i), format!("[{}] {}", i, entry));
+        }
+    }
+
+    #[test]
+    fn timelogday_from_str_mixed_dates() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 12:00:00";
+        assert!(matches!(
+            s.parse::<TimeLogDay>(),
+            Err(TimeLogError::ParseError(_))
+        ));
+        assert!("".parse::<TimeLogDay>().is_err());
+    }
+
+    #[test]
+    fn timelogday_worked_intervals() {
+        let day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                               2017/12/18 Mon | Vacation 12:00:00 13:00:00\n\
+                               2017/12/18 Mon | Work 13:00:00 UNDEF"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            day.worked_intervals(),
+            vec![(NaiveTime::from_hms(8, 0, 0), NaiveTime::from_hms(12, 0, 0))]
+        );
+        assert!(TimeLogDay::empty(day.date()).worked_intervals().is_empty());
+    }
+
+    #[test]
+    fn timelogday_open_entries() {
+        let day: TimeLogDay = "2017/12/18 Mon | Work UNDEF 07:00:00\n\
+                               2017/12/18 Mon | Work 08:00:00 10:00:00\n\
+                               2017/12/18 Mon | Work 12:00:00 UNDEF"
+            .parse()
+            .unwrap();
+        assert_eq!(day.open_entries(), vec![0, 2]);
+        assert!(day.has_unfinished_entries());
+
+        let day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 16:00:00".parse().unwrap();
+        assert!(day.open_entries().is_empty());
+        assert!(!day.has_unfinished_entries());
+    }
+
+    #[test]
+    fn timelogday_auto_lunch() {
+        let work = TimeLogEntryType::Work;
+        let lunch = AutoLunch {
+            deduct: Duration::minutes(30),
+            above: Duration::hours(6),
+        };
+        let day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 15:00:00".parse().unwrap();
+        let logged = day.logged_time(work, Duration::hours(8));
+        assert_eq!(logged, Duration::hours(7));
+        assert_eq!(day.apply_auto_lunch(None, work, logged), Duration::hours(7));
+        assert_eq!(
+            day.apply_auto_lunch(Some(lunch), work, logged),
+            Duration::minutes(6 * 60 + 30)
+        );
+
+        let with_break: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 11:00:00\n\
+                                      2017/12/18 Mon | Work 11:30:00 15:30:00"
+            .parse()
+            .unwrap();
+        let logged = with_break.logged_time(work, Duration::hours(8));
+        assert_eq!(
+            with_break.apply_auto_lunch(Some(lunch), work, logged),
+            Duration::hours(7)
+        );
+
+        let adjacent: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                                    2017/12/18 Mon | Work 12:00:00 15:00:00"
+            .parse()
+            .unwrap();
+        let logged = adjacent.logged_time(work, Duration::hours(8));
+        assert_eq!(
+            adjacent.apply_auto_lunch(Some(lunch), work, logged),
+            Duration::minutes(6 * 60 + 30)
+        );
+
+        let short_gap: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                                     2017/12/18 Mon | Work 12:05:00 15:05:00"
+            .parse()
+            .unwrap();
+        let logged = short_gap.logged_time(work, Duration::hours(8));
+        assert_eq!(
+            short_gap.apply_auto_lunch(Some(lunch), work, logged),
+            Duration::minutes(6 * 60 + 30)
+        );
+    }
+
     #[test]
     fn timelogday_time_logged_with() {
         let entries = vec![
@@ -771,7 +1794,11 @@ mod tests {
         let day: TimeLogDay = s.as_str().parse().unwrap();
         let etype = TimeLogEntryType::Work;
         assert_eq!(
-            day.time_logged_with(Some(NaiveTime::from_hms(8, 0, 0)), etype),
+            day.time_logged_with(
+                Some(NaiveTime::from_hms(8, 0, 0)),
+                etype,
+                Duration::hours(8)
+            ),
             Ok(Duration::minutes(89))
         );
     }
@@ -1,22 +1,37 @@
+use crate::clock::Clock;
 use crate::timelog::*;
 
-use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::io::BufWriter;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use chrono::prelude::*;
 use chrono::Duration;
 use chrono::NaiveTime;
 use chrono::Weekday;
 
-const MONTH_2_NDAYS: [u32; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+// Times before the boundary still belong to the previous day, for work that goes on past midnight
+pub fn logical_today(now: NaiveDateTime, boundary: Option<NaiveTime>) -> NaiveDate {
+    match boundary {
+        Some(b) if now.time() < b => now.date().pred(),
+        _ => now.date(),
+    }
+}
 
-fn get_monday_in_week_of(date: NaiveDate) -> NaiveDate {
+pub fn get_monday_in_week_of(date: NaiveDate) -> NaiveDate {
     let mut monday = date;
     while monday.weekday() != Weekday::Mon {
         monday = monday.pred();
@@ -25,7 +40,7 @@ fn get_monday_in_week_of(date: NaiveDate) -> NaiveDate {
     monday
 }
 
-fn get_sunday_in_week_of(date: NaiveDate) -> NaiveDate {
+pub fn get_sunday_in_week_of(date: NaiveDate) -> NaiveDate {
     let mut sunday = date;
     while sunday.weekday() != Weekday::Sun {
         sunday = sunday.succ();
@@ -39,20 +54,174 @@ fn get_first_day_in_month_of(date: NaiveDate) -> NaiveDate {
 }
 
 fn get_last_day_in_month_of(date: NaiveDate) -> NaiveDate {
-    NaiveDate::from_ymd(
-        date.year(),
-        date.month(),
-        MONTH_2_NDAYS[date.month0() as usize],
-    )
+    let (year, month) = match date.month() {
+        12 => (date.year() + 1, 1),
+        m => (date.year(), m + 1),
+    };
+    NaiveDate::from_ymd(year, month, 1).pred()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    Week,
+    Month,
+}
+
+impl FromStr for Grouping {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<Grouping> {
+        match s {
+            "week" => Ok(Grouping::Week),
+            "month" => Ok(Grouping::Month),
+            _ => Err(TimeLogError::inv_inp(
+                format!("Can't group by: {}", s).as_str(),
+            )),
+        }
+    }
+}
+
+impl Grouping {
+    fn bounds(&self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            Grouping::Week => (get_monday_in_week_of(date), get_sunday_in_week_of(date)),
+            Grouping::Month => (
+                get_first_day_in_month_of(date),
+                get_last_day_in_month_of(date),
+            ),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct TimeLogger {
     file_path: PathBuf,
     date2logday: HashMap<NaiveDate, TimeLogDay>,
+    utc: bool,
+    header: Vec<String>,
+    flex_resets: BTreeSet<FlexReset>,
+    time_precision: TimePrecision,
+    auto_lunch: Option<AutoLunch>,
+    max_backups: usize,
+    // Dates are always read with or without the weekday
+    write_weekday: bool,
+    flex_enabled: bool,
+    daily_loggable: Duration,
+    day_boundary: Option<NaiveTime>,
+    // Hash of the file contents as of the last load or save
+    disk_hash: Cell<Option<u64>>,
+    // Saves over changes made to the file after it was loaded
+    overwrite_changes: bool,
+    // Saves an empty log over a logfile with entries
+    allow_empty: bool,
+    // Entries were deleted on request, so an empty log is intended rather than a bug
+    entries_deleted: bool,
+}
+
+// In UTC, so that the names keep sorting in the order the files were made across DST changes
+fn backup_timestamp() -> String {
+    Utc::now().format("%Y%m%d%H%M%S%9f").to_string()
+}
+
+// Removes all but the `keep` newest of files, which are sorted oldest first
+fn prune_oldest(files: &[PathBuf], keep: usize) -> io::Result<()> {
+    let n_old = files.len().saturating_sub(keep);
+    for f in &files[..n_old] {
+        fs::remove_file(f)?;
+    }
+
+    Ok(())
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    Ok(hash_bytes(&fs::read(path)?))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DayDiff<'a> {
+    OnlyInSelf(&'a TimeLogDay),
+    OnlyInOther(&'a TimeLogDay),
+    Changed(&'a TimeLogDay, &'a TimeLogDay),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DayMerge {
+    Created,
+    Merged,
+}
+
+fn default_work_hours() -> (NaiveTime, NaiveTime) {
+    (NaiveTime::from_hms(8, 0, 0), NaiveTime::from_hms(16, 0, 0))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PeriodSummary {
+    pub logged: Duration,
+    pub loggable: Duration,
+    // Accumulated before the period, makes up part of the remaining time
+    pub flex: Duration,
+    pub remaining: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DaySummary {
+    pub worked: Duration,
+    pub loggable: Duration,
+    // Negative when more than the loggable time has been worked
+    pub remaining: Duration,
+    pub open_since: Option<NaiveTime>,
+}
+
+#[derive(Default)]
+pub struct BatchOptions {
+    pub weekday_only: bool,
+    pub work_hours: Option<(NaiveTime, NaiveTime)>,
+    pub hours_per_day: Option<Duration>,
+    // Leave days that already have entries as they are instead of failing
+    pub skip_existing: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BatchCounts {
+    pub added: usize,
+    pub skipped: usize,
+}
+
+pub struct Stats {
+    pub n_days: usize,
+    pub total: Duration,
+    // Indexed by days from monday, zero for weekdays without any worked days
+    pub avg_by_weekday: [Duration; 7],
+}
+
+// The earliest of the days that no other day beats, `days` being sorted by date
+fn first_by(
+    days: &[(NaiveDate, Duration)],
+    beats: fn(Duration, Duration) -> bool,
+) -> Option<(NaiveDate, Duration)> {
+    days.iter()
+        .copied()
+        .reduce(|best, day| if beats(day.1, best.1) { day } else { best })
+}
+
+impl Stats {
+    pub fn average(&self) -> Duration {
+        match self.n_days {
+            0 => Duration::zero(),
+            n => self.total / n as i32,
+        }
+    }
 }
 
 macro_rules! gen_time_between {
-    ($func: ident, $logday_getter: ident, $default_hrs: expr, $allow_weekend: expr) => {
+    // $default gives the time of a day without entries
+    ($func: ident, $logday_getter: ident, $default: expr, $allow_weekend: expr) => {
         fn $func(&self, day1: NaiveDate, day2: NaiveDate, etype: TimeLogEntryType) -> Duration {
             if day1 > day2 {
                 return Duration::seconds(0);
@@ -66,8 +235,8 @@ macro_rules! gen_time_between {
                         + self
                             .date2logday
                             .get(&date)
-                            .map(|x| x.$logday_getter(etype))
-                            .unwrap_or(Duration::hours($default_hrs));
+                            .map(|x| self.$logday_getter(x, etype))
+                            .unwrap_or_else(|| ($default)(self));
                 }
                 date = date.succ();
             }
@@ -91,9 +260,7 @@ macro_rules! gen_time_logged_in_timeperiod_with {
         pub fn $fname(&self, date: NaiveDate, with: Option<NaiveTime>) -> TimeLogResult<Duration> {
             let start_date = $start_date_f(date);
             let end_date = $end_date_f(date);
-            let logged_time = TimeLogEntryType::iterator()
-                .map(|x| self.compute_logged_time_between(start_date, end_date, *x))
-                .fold(Duration::hours(0), |acc, e| acc + e);
+            let logged_time = self.logged_time_between_where(start_date, end_date, |_| true);
 
             match with {
                 None => Ok(logged_time),
@@ -110,8 +277,9 @@ macro_rules! gen_time_logged_in_timeperiod_with {
 
                     let work_et = TimeLogEntryType::Work;
                     let last_tld = &self.date2logday[&last_date_with_entries];
-                    let last_tld_logged = last_tld.logged_time(work_et);
-                    let last_tld_logged_with = last_tld.time_logged_with(with, work_et)?;
+                    let last_tld_logged = self.day_logged_time(last_tld, work_et);
+                    let last_tld_logged_with =
+                        self.day_logged_time_with(last_tld, with, work_et)?;
                     Ok(logged_time - last_tld_logged + last_tld_logged_with)
                 }
             }
@@ -119,18 +287,30 @@ macro_rules! gen_time_logged_in_timeperiod_with {
     };
 }
 
-macro_rules! gen_time_left_in_timeperiod_with {
-    ($fname: ident, $loggable_f: ident, $logged_f: ident) => {
+macro_rules! gen_period_summary_with {
+    ($fname: ident, $loggable_f: ident, $logged_f: ident, $first_day: ident, $last_day: ident) => {
         pub fn $fname(
             &self,
             date: NaiveDate,
             with: Option<NaiveTime>,
-        ) -> TimeLogResult<(Duration, Duration)> {
-            let etype = TimeLogEntryType::Work;
-            let workable_time = self.$loggable_f(date, etype);
-            let logged_time = self.$logged_f(date, with)?;
-            let flex_time = self.flextime_as_of(date);
-            Ok((workable_time - logged_time + flex_time, flex_time))
+        ) -> TimeLogResult<PeriodSummary> {
+            let loggable = self.$loggable_f(date, TimeLogEntryType::Work);
+            let logged = self.$logged_f(date, with)?;
+            let overtime = self.compute_logged_time_between(
+                $first_day(date),
+                $last_day(date),
+                TimeLogEntryType::Overtime,
+            );
+            let flex = match self.flex_enabled {
+                true => self.flextime_as_of(date),
+                false => Duration::zero(),
+            };
+            Ok(PeriodSummary {
+                logged,
+                loggable,
+                flex,
+                remaining: loggable - (logged - overtime) + flex,
+            })
         }
     };
 }
@@ -145,7 +325,7 @@ macro_rules! gen_verify_entries {
             let mut bad_entries = Vec::new();
             while cur != end_date {
                 if let Some(tld) = self.date2logday.get(&cur) {
-                    if tld.has_unfinished_entries() {
+                    if tld.has_unfinished_entries() || tld.validate().is_err() {
                         bad_entries.push(cur);
                     }
                 }
@@ -161,39 +341,105 @@ macro_rules! gen_verify_entries {
 }
 
 const TIMELOGGER_FILE: &str = ".timelog";
+const UTC_HEADER: &str = "# timelog: utc";
+pub const DEFAULT_MAX_BACKUPS: usize = 5;
 impl TimeLogger {
-    fn write_entries(&self) -> String {
-        let mut s = String::new();
+    fn new(file_path: PathBuf) -> Self {
+        TimeLogger {
+            file_path,
+            date2logday: HashMap::new(),
+            utc: false,
+            header: Vec::new(),
+            flex_resets: BTreeSet::new(),
+            time_precision: TimePrecision::default(),
+            auto_lunch: None,
+            max_backups: DEFAULT_MAX_BACKUPS,
+            write_weekday: true,
+            flex_enabled: true,
+            daily_loggable: Duration::hours(8),
+            day_boundary: None,
+            disk_hash: Cell::new(None),
+            overwrite_changes: false,
+            allow_empty: false,
+            entries_deleted: false,
+        }
+    }
+
+    fn write_line<W: Write, T: Display>(&self, w: &mut W, x: &T) -> io::Result<()> {
+        match self.write_weekday {
+            true => writeln!(w, "{}", x),
+            false => writeln!(w, "{:#}", x),
+        }
+    }
+
+    fn write_entries_to<W: Write>(&self, mut w: W) -> TimeLogResult<()> {
+        if self.utc {
+            writeln!(w, "{}", UTC_HEADER)?;
+        }
+        for line in &self.header {
+            writeln!(w, "{}", line)?;
+        }
+
         let mut dates: Vec<&NaiveDate> = self.date2logday.keys().collect();
         dates.sort();
+        let mut resets = self.flex_resets.iter().peekable();
         for date in dates {
-            s.push_str(self.date2logday[date].to_string().as_str());
-            s.push('\n');
+            while let Some(reset) = resets.next_if(|r| r.date() <= *date) {
+                self.write_line(&mut w, reset)?;
+            }
+            let tld = &self.date2logday[date];
+            if !tld.is_empty() {
+                self.write_line(&mut w, tld)?;
+            }
         }
+        for reset in resets {
+            self.write_line(&mut w, reset)?;
+        }
+
+        w.flush()?;
+        Ok(())
+    }
 
-        s
+    pub fn write_entries(&self) -> String {
+        let mut buf = Vec::new();
+        self.write_entries_to(&mut buf)
+            .expect("Writing to a Vec can't fail");
+        String::from_utf8(buf).expect("Entries are valid UTF-8")
     }
 
     fn read_entries(&mut self, s: &str) -> TimeLogResult<()> {
+        // Comments before the first entry make up the header, which is kept on write.
+        // Any later comments are dropped.
+        let mut in_header = true;
         for line in s.lines() {
-            let tle: TimeLogEntry = line.parse()?;
-            let date = tle.date();
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
 
-            let tld = match self.date2logday.entry(date) {
-                Vacant(entry) => entry.insert(TimeLogDay::empty(date)),
-                Occupied(entry) => entry.into_mut(),
-            };
+            if line.starts_with('#') {
+                if line == UTC_HEADER {
+                    self.utc = true;
+                } else if in_header {
+                    self.header.push(line.to_string());
+                }
+                continue;
+            }
+
+            in_header = false;
+            if let Ok(reset) = line.parse::<FlexReset>() {
+                self.flex_resets.insert(reset);
+                continue;
+            }
 
-            tld.add_entry(tle);
+            let tle: TimeLogEntry = line.parse()?;
+            self.day_mut(tle.date()).add_entry(tle);
         }
         Ok(())
     }
 
-    fn from_file(path_buf: PathBuf) -> TimeLogResult<Self> {
-        let mut tl = TimeLogger {
-            file_path: path_buf,
-            date2logday: HashMap::new(),
-        };
+    pub fn from_file(path_buf: PathBuf) -> TimeLogResult<Self> {
+        let mut tl = TimeLogger::new(path_buf);
         if !tl.file_path.as_path().exists() {
             let dirs = tl.file_path.parent().ok_or_else(|| {
                 TimeLogError::InvalidInputError(format!(
@@ -203,6 +449,7 @@ impl TimeLogger {
             })?;
             std::fs::create_dir_all(dirs)?;
             File::create(tl.file_path.as_path())?;
+            tl.disk_hash.set(Some(hash_bytes(&[])));
         } else {
             let file = File::open(tl.file_path.as_path())?;
 
@@ -210,6 +457,9 @@ impl TimeLogger {
             let mut contents = String::new();
             buf_reader.read_to_string(&mut contents)?;
             tl.read_entries(contents.as_str())?;
+            // Hashing what was parsed, rather than reading the file again, so that an edit in
+            // between is still detected
+            tl.disk_hash.set(Some(hash_bytes(contents.as_bytes())));
         }
 
         Ok(tl)
@@ -222,8 +472,192 @@ impl TimeLogger {
         TimeLogger::from_file(path_buf)
     }
 
-    gen_time_between!(compute_logged_time_between, logged_time, 0, true);
-    gen_time_between!(compute_loggable_time_between, loggable_time, 8, false);
+    pub fn is_empty(&self) -> bool {
+        self.date2logday.is_empty()
+    }
+
+    pub fn days_count(&self) -> usize {
+        self.date2logday.len()
+    }
+
+    pub fn entries_count(&self) -> usize {
+        self.date2logday
+            .values()
+            .map(|tld| tld.entries().len())
+            .sum()
+    }
+
+    pub fn is_utc(&self) -> bool {
+        self.utc
+    }
+
+    pub fn set_utc(&mut self) -> TimeLogResult<()> {
+        if !self.is_empty() {
+            return Err(TimeLogError::inv_inp(
+                "Existing entries need to be migrated to be stored in UTC",
+            ));
+        }
+        self.utc = true;
+        Ok(())
+    }
+
+    pub fn set_time_precision(&mut self, precision: TimePrecision) {
+        self.time_precision = precision;
+    }
+
+    pub fn set_auto_lunch(&mut self, lunch: Option<AutoLunch>) {
+        self.auto_lunch = lunch;
+    }
+
+    pub fn set_write_weekday(&mut self, write_weekday: bool) {
+        self.write_weekday = write_weekday;
+    }
+
+    // Without flex, time worked in earlier periods doesn't affect the remaining time
+    pub fn set_flex_enabled(&mut self, enabled: bool) {
+        self.flex_enabled = enabled;
+    }
+
+    pub fn set_overwrite_changes(&mut self, overwrite: bool) {
+        self.overwrite_changes = overwrite;
+    }
+
+    pub fn set_allow_empty(&mut self, allow_empty: bool) {
+        self.allow_empty = allow_empty;
+    }
+
+    pub fn set_daily_loggable(&mut self, daily_loggable: Duration) {
+        self.daily_loggable = daily_loggable;
+    }
+
+    pub fn set_max_backups(&mut self, max_backups: usize) {
+        self.max_backups = max_backups;
+    }
+
+    pub fn set_day_boundary(&mut self, boundary: Option<NaiveTime>) {
+        self.day_boundary = boundary;
+    }
+
+    // Where an entry started or ended at time is logged, when the command is run at now. A time
+    // before the day boundary is after midnight and is logged on the day it happened, any other
+    // time is on the logical day. See logical_today.
+    pub fn log_datetime(&self, now: NaiveDateTime, time: NaiveTime) -> NaiveDateTime {
+        let date = match self.day_boundary {
+            Some(b) if time < b => now.date(),
+            _ => logical_today(now, self.day_boundary),
+        };
+        self.storage_datetime(date, time)
+    }
+
+    // With a day boundary, an end before the boundary is after midnight on the logical day
+    // that the entry was started on
+    pub fn ends_past_midnight(&self, start: NaiveTime, end: NaiveTime) -> bool {
+        self.day_boundary.is_some_and(|b| end < b && b <= start)
+    }
+
+    pub fn storage_datetime(&self, date: NaiveDate, time: NaiveTime) -> NaiveDateTime {
+        let local = NaiveDateTime::new(date, time);
+        if !self.utc {
+            return local;
+        }
+
+        match Local.from_local_datetime(&local).earliest() {
+            Some(x) => x.naive_utc(),
+            // Skipped by a DST transition, fall back to the current offset
+            None => local - Duration::seconds(Local::now().offset().local_minus_utc() as i64),
+        }
+    }
+
+    pub fn migrate_to_utc(&mut self, offset: FixedOffset) -> TimeLogResult<()> {
+        if self.utc {
+            return Err(TimeLogError::inv_inp(
+                "The timelog is already stored in UTC",
+            ));
+        }
+
+        let by = -Duration::seconds(offset.local_minus_utc() as i64);
+        let mut date2logday = HashMap::new();
+        for tld in self.date2logday.values() {
+            for entry in tld.entries() {
+                let shifted = entry.shifted(by)?;
+                let date = shifted.date();
+                date2logday
+                    .entry(date)
+                    .or_insert_with(|| TimeLogDay::empty(date))
+                    .add_entry(shifted);
+            }
+        }
+
+        self.date2logday = date2logday;
+        self.utc = true;
+        Ok(())
+    }
+
+    fn day_logged_time(&self, tld: &TimeLogDay, etype: TimeLogEntryType) -> Duration {
+        tld.apply_auto_lunch(
+            self.auto_lunch,
+            etype,
+            tld.logged_time(etype, self.daily_loggable),
+        )
+    }
+
+    // Holiday time counts on a day that is partly worked, but not on a day off, as that has no
+    // loggable time for it to count toward. pred picks among the other types.
+    fn day_logged_time_where<P>(&self, tld: &TimeLogDay, pred: P) -> Duration
+    where
+        P: Fn(TimeLogEntryType) -> bool,
+    {
+        TimeLogEntryType::iterator()
+            .filter(|x| match x.is_day_off() {
+                true => !tld.is_day_off(),
+                false => pred(**x),
+            })
+            .map(|x| self.day_logged_time(tld, *x))
+            .fold(Duration::zero(), |acc, e| acc + e)
+    }
+
+    fn logged_time_between_where<P>(&self, day1: NaiveDate, day2: NaiveDate, pred: P) -> Duration
+    where
+        P: Fn(TimeLogEntryType) -> bool,
+    {
+        let mut date = day1;
+        let mut sum = Duration::zero();
+        while date <= day2 {
+            if let Some(tld) = self.date2logday.get(&date) {
+                sum = sum + self.day_logged_time_where(tld, &pred);
+            }
+            date = date.succ();
+        }
+
+        sum
+    }
+
+    fn day_logged_time_with(
+        &self,
+        tld: &TimeLogDay,
+        with: Option<NaiveTime>,
+        etype: TimeLogEntryType,
+    ) -> TimeLogResult<Duration> {
+        let logged = tld.time_logged_with(with, etype, self.daily_loggable)?;
+        Ok(tld.apply_auto_lunch(self.auto_lunch, etype, logged))
+    }
+
+    fn day_loggable_time(&self, tld: &TimeLogDay, _etype: TimeLogEntryType) -> Duration {
+        tld.loggable_time(self.daily_loggable)
+    }
+
+    gen_time_between!(
+        compute_logged_time_between,
+        day_logged_time,
+        |_: &Self| Duration::zero(),
+        true
+    );
+    gen_time_between!(
+        compute_loggable_time_between,
+        day_loggable_time,
+        |tl: &Self| tl.daily_loggable,
+        false
+    );
 
     gen_x_in_y_of!(
         compute_loggable_time_in_month_of,
@@ -240,14 +674,8 @@ impl TimeLogger {
 
     fn log_with(&mut self, date: NaiveDate, time: NaiveTime, mutator: fn(&mut TimeLogDay, NaiveTime, TimeLogEntryType) -> TimeLogEntry) -> TimeLogEntry { 
      let entry_type = TimeLogEntryType::Work;
-        let time = NaiveTime::from_hms(time.hour(), time.minute(), time.second());
-
-        let tld = match self.date2logday.entry(date) {
-            Vacant(entry) => entry.insert(TimeLogDay::empty(date)),
-            Occupied(entry) => entry.into_mut(),
-        };
-
-        mutator(tld, time, entry_type)
+        let time = self.time_precision.truncate(time);
+        mutator(self.day_mut(date), time, entry_type)
     }
 
     pub fn log_start(&mut self, date: NaiveDate, time: NaiveTime) -> TimeLogEntry {
@@ -258,146 +686,882 @@ impl TimeLogger {
         self.log_with(date, time, TimeLogDay::set_end)
     }
 
-    fn flextime_as_of(&self, date: NaiveDate) -> Duration {
-        let mut keys: Vec<&NaiveDate> = self.date2logday.keys().collect();
-        keys.sort();
-
-        if keys.is_empty() {
-            return Duration::hours(0);
+    // Entries can't span midnight, so the open entry on the day before date is ended at
+    // midnight and the time after it is logged as an entry of its own on date. See
+    // ends_past_midnight.
+    pub fn log_end_past_midnight(&mut self, date: NaiveDate, time: NaiveTime) -> TimeLogEntry {
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+        let ended = self
+            .day_mut(date.pred())
+            .set_end(midnight, TimeLogEntryType::Work);
+        if self.time_precision.truncate(time) == midnight {
+            return ended;
         }
 
-        let mut prev_week_sunday = match date.weekday() {
-            Weekday::Sun => date.pred(),
-            _ => date,
-        };
-
-        while prev_week_sunday.weekday() != Weekday::Sun {
-            prev_week_sunday = prev_week_sunday.pred();
-        }
+        self.log_start(date, midnight);
+        self.log_end(date, time)
+    }
 
-        if prev_week_sunday <= *keys[0] {
-            // If this is true, we have no entries to calculate flex time for
-            return Duration::hours(0);
+    pub fn compute_logged_time_for_project_between(
+        &self,
+        day1: NaiveDate,
+        day2: NaiveDate,
+        etype: TimeLogEntryType,
+        project: Option<&str>,
+    ) -> Duration {
+        let mut date = day1;
+        let mut sum = Duration::seconds(0);
+        while date <= day2 {
+            if let Some(tld) = self.date2logday.get(&date) {
+                sum = sum + tld.logged_time_for_project(etype, project, self.daily_loggable);
+            }
+            date = date.succ();
         }
 
-        let start_date = *keys[0];
-        let end_date = prev_week_sunday;
-        let logged_time = TimeLogEntryType::iterator()
-            .map(|x| self.compute_logged_time_between(start_date, end_date, *x))
-            .fold(Duration::hours(0), |acc, e| acc + e);
+        sum
+    }
 
-        self.compute_loggable_time_between(start_date, end_date, TimeLogEntryType::Work)
-            - logged_time
+    // Sorted in the declaration order of TimeLogEntryType, not the order of iterator()
+    pub fn totals_by_type(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Vec<(TimeLogEntryType, Duration)> {
+        let mut totals: Vec<(TimeLogEntryType, Duration)> = TimeLogEntryType::iterator()
+            .map(|x| (*x, self.compute_logged_time_between(from, to, *x)))
+            .filter(|(_, dur)| *dur > Duration::zero())
+            .collect();
+        totals.sort_by_key(|(ty, _)| *ty);
+        totals
     }
 
-    pub fn time_logged_at_date_with(
+    // Only the part of each interval inside `window` counts, while leave without times is a
+    // full day. The auto lunch comes out of the first Work time inside the window, the same
+    // total as in the week when the window is the whole day. Entries for which `key` gives
+    // None are skipped.
+    pub fn totals_within<K: Ord>(
         &self,
-        date: NaiveDate,
-        with: Option<NaiveTime>,
-    ) -> TimeLogResult<Duration> {
+        from: NaiveDate,
+        to: NaiveDate,
+        window: (NaiveTime, NaiveTime),
+        key: impl Fn(&TimeLogEntry) -> Option<K>,
+    ) -> Vec<(K, Duration)> {
+        let mut totals: BTreeMap<K, Duration> = BTreeMap::new();
         let etype = TimeLogEntryType::Work;
-        let tld = self.date2logday.get(&date).ok_or_else(|| {
-            TimeLogError::inv_inp(
-                format!("Can't find start time, no entries for date: {}\n", date).as_str(),
-            )
-        })?;
+        let mut date = from;
+        while date <= to {
+            if let Some(tld) = self.date2logday.get(&date) {
+                let logged = tld.logged_time(etype, self.daily_loggable);
+                let mut lunch = logged - tld.apply_auto_lunch(self.auto_lunch, etype, logged);
+                for e in tld.entries() {
+                    let mut dur = match e.duration() {
+                        None if !e.ty().counts_as_worked() => self.daily_loggable,
+                        _ => e.duration_within(window),
+                    };
+                    if e.ty() == etype {
+                        let deducted = lunch.min(dur);
+                        dur = dur - deducted;
+                        lunch = lunch - deducted;
+                    }
+                    if let Some(k) = key(e).filter(|_| dur > Duration::zero()) {
+                        let total = totals.entry(k).or_insert_with(Duration::zero);
+                        *total = *total + dur;
+                    }
+                }
+            }
+            date = date.succ();
+        }
 
-        tld.time_logged_with(with, etype)
+        totals.into_iter().collect()
     }
 
-    gen_time_logged_in_timeperiod_with!(
-        time_logged_in_week_of_with,
-        get_monday_in_week_of,
-        get_sunday_in_week_of
-    );
-    gen_time_logged_in_timeperiod_with!(
-        time_logged_in_month_of_with,
-        get_first_day_in_month_of,
-        get_last_day_in_month_of
-    );
-
-    gen_time_left_in_timeperiod_with!(
-        time_left_in_week_of_with,
-        compute_loggable_time_in_week_of,
-        time_logged_in_week_of_with
-    );
-    gen_time_left_in_timeperiod_with!(
-        time_left_in_month_of_with,
-        compute_loggable_time_in_month_of,
-        time_logged_in_month_of_with
-    );
-
-    pub fn save(&self) -> TimeLogResult<()> {
-        let mut bkp = self.file_path.clone();
-        bkp.set_extension("tl.bkp");
-        let bkp_fp = bkp.as_path();
-        let fp: &Path = self.file_path.as_path();
-        debug_assert!(fp.exists(), "logfile does not exist");
-        let mut file = File::create(fp)?;
-        let s = self.write_entries();
-        fs::copy(fp, bkp_fp)?;
-        match file.write_all(s.as_bytes()) {
-            Ok(_) => {
-                fs::remove_file(bkp_fp)?;
-                Ok(())
-            }
-            Err(ref e) => {
-                fs::copy(bkp_fp, fp)?;
-                Err(TimeLogError::io_error_extra_msg(
-                    e,
-                    format!("Failed to write to file (restoring backup): {}", e).as_str(),
-                ))
+    pub fn totals_by_project(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Vec<(Option<String>, Duration)> {
+        let mut projects: Vec<Option<String>> = Vec::new();
+        let mut date = from;
+        while date <= to {
+            if let Some(tld) = self.date2logday.get(&date) {
+                for e in tld.filtered_by_type(TimeLogEntryType::Work) {
+                    let project = e.project().map(String::from);
+                    if !projects.contains(&project) {
+                        projects.push(project);
+                    }
+                }
             }
+            date = date.succ();
         }
+        projects.sort();
+
+        projects
+            .into_iter()
+            .map(|p| {
+                let dur = self.compute_logged_time_for_project_between(
+                    from,
+                    to,
+                    TimeLogEntryType::Work,
+                    p.as_deref(),
+                );
+                (p, dur)
+            })
+            .collect()
     }
 
-    pub fn get_latest_n_entries(&self, n: usize) -> Vec<&TimeLogDay> {
-        let mut days = Vec::with_capacity(n);
-        let mut keys: Vec<&NaiveDate> = self.date2logday.keys().collect();
-        keys.sort();
+    pub fn date_range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let first = self.date2logday.keys().min()?;
+        let last = self.date2logday.keys().max()?;
+        Some((*first, *last))
+    }
 
-        for (i, k) in keys.iter().rev().enumerate() {
-            if i >= n {
-                break;
+    // The surplus, or deficit if negative, at the end of the week or month of date if each
+    // unlogged workday after date is logged at the average of the workdays before it. date
+    // itself may still be open, so it only counts with the time logged so far.
+    pub fn project_period(&self, date: NaiveDate, grouping: Grouping) -> Duration {
+        let (first, last) = grouping.bounds(date);
+        let counts = |x: TimeLogEntryType| x.counts_toward_target();
+        let mut worked = Duration::zero();
+        let mut n_days = 0;
+        let mut days_left = 0;
+        let mut cur = first;
+        while cur <= last {
+            match self.date2logday.get(&cur) {
+                Some(tld)
+                    if cur < date && tld.loggable_time(self.daily_loggable) > Duration::zero() =>
+                {
+                    worked = worked + self.day_logged_time_where(tld, counts);
+                    n_days += 1;
+                }
+                None if cur > date
+                    && TimeLogDay::empty(cur).loggable_time(self.daily_loggable)
+                        > Duration::zero() =>
+                {
+                    days_left += 1;
+                }
+                _ => (),
             }
-            days.push(&self.date2logday[k]);
+            cur = cur.succ();
         }
 
-        days.reverse();
-
-        days
+        let average = match n_days {
+            0 => Duration::zero(),
+            n => worked / n,
+        };
+        let logged = self.logged_time_between_where(first, last, counts);
+        let loggable = self.compute_loggable_time_between(first, last, TimeLogEntryType::Work);
+        logged + average * days_left - loggable
     }
 
-    pub fn batch_add(
-        &mut self,
-        ty: TimeLogEntryType,
+    // Time logged per week or month, with the first and last group clipped to the range
+    pub fn grouped_totals(
+        &self,
         from: NaiveDate,
         to: NaiveDate,
-        weekday_only: bool,
-    ) -> TimeLogResult<()> {
-        assert!(from < to);
-        let mut cur = from;
-        while cur < to {
-            if weekday_only && !is_weekday(cur) {
-                cur = cur.succ();
-                continue;
-            }
+        grouping: Grouping,
+    ) -> Vec<(NaiveDate, NaiveDate, Duration)> {
+        let mut groups = Vec::new();
+        let mut start = from;
+        while start <= to {
+            let end = grouping.bounds(start).1.min(to);
+            let logged = self.logged_time_between_where(start, end, |_| true);
+            groups.push((start, end, logged));
+            start = end.succ();
+        }
 
-            match self.date2logday.entry(cur) {
-                Occupied(logday) => {
-                    return Err(TimeLogError::inv_inp(
-                        format!("There is already an entry for {}: {}", cur, logday.key()).as_str(),
-                    ));
-                }
-                Vacant(vacant) => {
-                    vacant.insert(TimeLogDay::full(cur, ty));
+        groups
+    }
+
+    // Days without any worked time are left out
+    pub fn worked_per_day(&self, from: NaiveDate, to: NaiveDate) -> Vec<(NaiveDate, Duration)> {
+        let mut days = Vec::new();
+        let mut date = from;
+        while date <= to {
+            if let Some(tld) = self.date2logday.get(&date) {
+                let worked = self.day_logged_time(tld, TimeLogEntryType::Work);
+                if worked > Duration::zero() {
+                    days.push((date, worked));
                 }
-            };
+            }
+            date = date.succ();
+        }
 
-            cur = cur.succ();
+        days
+    }
+
+    // Indexed by days from monday
+    pub fn worked_by_weekday(&self, from: NaiveDate, to: NaiveDate) -> [Duration; 7] {
+        let mut buckets = [Duration::zero(); 7];
+        for (date, dur) in self.worked_per_day(from, to) {
+            let i = date.weekday().num_days_from_monday() as usize;
+            buckets[i] = buckets[i] + dur;
         }
 
-        Ok(())
+        buckets
+    }
+
+    pub fn busiest_day(&self, from: NaiveDate, to: NaiveDate) -> Option<(NaiveDate, Duration)> {
+        first_by(&self.worked_per_day(from, to), |a, b| a > b)
+    }
+
+    pub fn quietest_day(&self, from: NaiveDate, to: NaiveDate) -> Option<(NaiveDate, Duration)> {
+        first_by(&self.worked_per_day(from, to), |a, b| a < b)
+    }
+
+    pub fn stats(&self, from: NaiveDate, to: NaiveDate) -> Stats {
+        let days = self.worked_per_day(from, to);
+        let mut avg_by_weekday = self.worked_by_weekday(from, to);
+        for (i, avg) in avg_by_weekday.iter_mut().enumerate() {
+            let n = days
+                .iter()
+                .filter(|(date, _)| date.weekday().num_days_from_monday() as usize == i)
+                .count();
+            if n > 0 {
+                *avg = *avg / n as i32;
+            }
+        }
+
+        Stats {
+            n_days: days.len(),
+            total: days
+                .iter()
+                .fold(Duration::zero(), |acc, (_, dur)| acc + *dur),
+            avg_by_weekday,
+        }
+    }
+
+    pub fn rename_project(&mut self, old: &str, new: &str) -> usize {
+        self.date2logday
+            .values_mut()
+            .map(|tld| tld.rename_project(old, new))
+            .sum()
+    }
+
+    // Uses the same index as `view --index`. The day is removed when its last entry is.
+    pub fn delete_entry(&mut self, date: NaiveDate, index: usize) -> Option<TimeLogEntry> {
+        let tld = self.date2logday.get_mut(&date)?;
+        let removed = tld.remove_entry(index);
+        if tld.is_empty() {
+            self.date2logday.remove(&date);
+        }
+        self.entries_deleted |= removed.is_some();
+
+        removed
+    }
+
+    fn day_mut(&mut self, date: NaiveDate) -> &mut TimeLogDay {
+        self.date2logday
+            .entry(date)
+            .or_insert_with(|| TimeLogDay::empty(date))
+    }
+
+    // Entries that are already logged on the date are not added again. Nothing is merged if
+    // any entry overlaps one that is already logged.
+    pub fn merge_day(&mut self, day: TimeLogDay) -> TimeLogResult<DayMerge> {
+        let (mut tld, merge) = match self.date2logday.get(&day.date()) {
+            Some(tld) => (tld.clone(), DayMerge::Merged),
+            None => (TimeLogDay::empty(day.date()), DayMerge::Created),
+        };
+
+        for entry in day.entries() {
+            if !tld.entries().contains(entry) {
+                tld.try_add_entry(entry.clone())?;
+            }
+        }
+        self.date2logday.insert(day.date(), tld);
+
+        Ok(merge)
+    }
+
+    pub fn delete_entries_where(
+        &mut self,
+        date: NaiveDate,
+        pred: impl Fn(&TimeLogEntry) -> bool,
+    ) -> usize {
+        let tld = match self.date2logday.get_mut(&date) {
+            Some(x) => x,
+            None => return 0,
+        };
+        let removed = tld.remove_entries_where(pred);
+        if tld.is_empty() {
+            self.date2logday.remove(&date);
+        }
+        self.entries_deleted |= removed > 0;
+
+        removed
+    }
+
+    // Newest first, ordered by date and then by start, or end for entries without a start
+    pub fn recent_entries(&self, n: usize) -> Vec<&TimeLogEntry> {
+        let mut entries: Vec<&TimeLogEntry> = self
+            .date2logday
+            .values()
+            .flat_map(|tld| tld.entries())
+            .collect();
+        entries.sort_by_key(|e| (e.date(), e.start().or(e.end())));
+        entries.into_iter().rev().take(n).collect()
+    }
+
+    // Drops all entries and flex resets, the header is kept
+    pub fn clear(&mut self) {
+        self.date2logday.clear();
+        self.flex_resets.clear();
+    }
+
+    // Merges adjacent intervals in every day and drops days left empty. Returns the
+    // number of entries removed.
+    pub fn compact(&mut self) -> usize {
+        let removed = self
+            .date2logday
+            .values_mut()
+            .map(|tld| tld.merge_adjacent())
+            .sum();
+        self.date2logday.retain(|_, tld| !tld.is_empty());
+
+        removed
+    }
+
+    pub fn reset_flex(&mut self, date: NaiveDate) {
+        self.flex_resets.insert(FlexReset::new(date));
+    }
+
+    pub fn flextime_as_of(&self, date: NaiveDate) -> Duration {
+        let mut keys: Vec<&NaiveDate> = self.date2logday.keys().collect();
+        keys.sort();
+
+        if keys.is_empty() {
+            return Duration::hours(0);
+        }
+
+        let mut prev_week_sunday = match date.weekday() {
+            Weekday::Sun => date.pred(),
+            _ => date,
+        };
+
+        while prev_week_sunday.weekday() != Weekday::Sun {
+            prev_week_sunday = prev_week_sunday.pred();
+        }
+
+        let start_date = self.flex_start(date, *keys[0]);
+
+        if prev_week_sunday <= start_date {
+            // If this is true, we have no entries to calculate flex time for
+            return Duration::hours(0);
+        }
+
+        self.flex_between(start_date, prev_week_sunday)
+    }
+
+    // The day the flex as of date is counted from, the latest reset or else the first entry
+    fn flex_start(&self, date: NaiveDate, first: NaiveDate) -> NaiveDate {
+        match self.flex_resets.range(..=FlexReset::new(date)).next_back() {
+            Some(reset) if reset.date() > first => reset.date(),
+            _ => first,
+        }
+    }
+
+    fn flex_between(&self, from: NaiveDate, to: NaiveDate) -> Duration {
+        let logged_time = self.logged_time_between_where(from, to, |x| x.counts_toward_target());
+
+        self.compute_loggable_time_between(from, to, TimeLogEntryType::Work) - logged_time
+    }
+
+    fn open_entry_start_at(&self, date: NaiveDate) -> TimeLogResult<NaiveTime> {
+        self.date2logday
+            .get(&date)
+            .and_then(|tld| tld.open_entry_start())
+            .ok_or_else(|| {
+                TimeLogError::inv_inp(format!("No open entry for date: {}", date).as_str())
+            })
+    }
+
+    // The flex as of the end of each week from the first to the last logged one, by Sunday
+    pub fn weekly_flex_series(&self) -> Vec<(NaiveDate, Duration)> {
+        let (first, last) = match self.date_range() {
+            Some(x) => x,
+            None => return Vec::new(),
+        };
+
+        // The same as flextime_as_of the Monday after each Sunday, but the balance is carried
+        // over from week to week until a reset instead of recounted from the start
+        let mut series = Vec::new();
+        let mut start = first;
+        let mut next = first;
+        let mut flex = Duration::zero();
+        let mut sunday = get_sunday_in_week_of(first);
+        while sunday <= get_sunday_in_week_of(last) {
+            let reset = self.flex_start(sunday.succ(), first);
+            if reset != start {
+                start = reset;
+                next = reset;
+                flex = Duration::zero();
+            }
+            if sunday > start {
+                flex = flex + self.flex_between(next, sunday);
+                next = sunday.succ();
+            }
+            series.push((sunday, flex));
+            sunday += Duration::weeks(1);
+        }
+
+        series
+    }
+
+    // The end time an open Work entry at date would have after dur
+    pub fn open_entry_end_after(&self, date: NaiveDate, dur: Duration) -> TimeLogResult<NaiveTime> {
+        let start = self.open_entry_start_at(date)?;
+
+        match start.overflowing_add_signed(dur) {
+            (end, 0) => Ok(end),
+            _ => Err(TimeLogError::inv_inp(
+                format!("Open entry starting at {} would end past midnight", start).as_str(),
+            )),
+        }
+    }
+
+    // The date and start of the open Work entry that an end at `end` ends, on the day before
+    // for an end past midnight. See ends_past_midnight.
+    pub fn open_entry_before(&self, end: NaiveDateTime) -> Option<(NaiveDate, NaiveTime)> {
+        let open_at = |date: NaiveDate| self.day(date).and_then(TimeLogDay::open_entry_start);
+        let date = end.date();
+        match open_at(date) {
+            Some(start) => Some((date, start)),
+            None => open_at(date.pred())
+                .filter(|start| self.ends_past_midnight(*start, end.time()))
+                .map(|start| (date.pred(), start)),
+        }
+    }
+
+    // The start of the open Work entry that an end at `end` ends and how long it would be
+    pub fn open_entry_duration(&self, end: NaiveDateTime) -> TimeLogResult<(NaiveTime, Duration)> {
+        let (date, start) = self.open_entry_before(end).ok_or_else(|| {
+            TimeLogError::inv_inp(format!("No open entry for date: {}", end.date()).as_str())
+        })?;
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+        if date != end.date() {
+            let before = Duration::days(1) - start.signed_duration_since(midnight);
+            let after = end.time().signed_duration_since(midnight);
+            return Ok((start, before + after));
+        }
+        let end = end.time();
+        if end < start {
+            return Err(TimeLogError::inv_inp(
+                format!("Open entry starts at {}, after {}", start, end).as_str(),
+            ));
+        }
+
+        Ok((start, end.signed_duration_since(start)))
+    }
+
+    pub fn time_logged_at_date_with(
+        &self,
+        date: NaiveDate,
+        with: Option<NaiveTime>,
+    ) -> TimeLogResult<Duration> {
+        let etype = TimeLogEntryType::Work;
+        let tld = self.date2logday.get(&date).ok_or_else(|| {
+            TimeLogError::inv_inp(
+                format!("Can't find start time, no entries for date: {}\n", date).as_str(),
+            )
+        })?;
+
+        self.day_logged_time_with(tld, with, etype)
+    }
+
+    // Counts a started entry as ending now, on the logical day. See logical_today.
+    pub fn logged_time_today(&self, clock: &dyn Clock) -> TimeLogResult<Duration> {
+        let now = self.storage_datetime(clock.today(), clock.now().time());
+        let today = logical_today(now, self.day_boundary);
+        if today == now.date() {
+            return self.time_logged_at_date_with(today, Some(now.time()));
+        }
+
+        // Past midnight, so an entry started on the logical day is open until now
+        let open = match self.open_entry_before(now) {
+            Some((date, _)) if date == today => self.open_entry_duration(now)?.1,
+            _ => Duration::zero(),
+        };
+        let tld = self.date2logday.get(&today).ok_or_else(|| {
+            TimeLogError::inv_inp(format!("No entries for date: {}", today).as_str())
+        })?;
+        let etype = TimeLogEntryType::Work;
+        let logged = tld.time_logged_with(None, etype, self.daily_loggable)? + open;
+        Ok(tld.apply_auto_lunch(self.auto_lunch, etype, logged))
+    }
+
+    // The summary of the logical day, counting a started entry as ending now
+    pub fn status(&self, clock: &dyn Clock) -> TimeLogResult<DaySummary> {
+        let now = self.storage_datetime(clock.today(), clock.now().time());
+        let work = self.logged_time_today(clock)?;
+        Ok(self.day_summary(logical_today(now, self.day_boundary), work))
+    }
+
+    pub fn today_summary(
+        &self,
+        today: NaiveDate,
+        with: Option<NaiveTime>,
+    ) -> TimeLogResult<DaySummary> {
+        let work = self.time_logged_at_date_with(today, with)?;
+        Ok(self.day_summary(today, work))
+    }
+
+    // date must have entries
+    fn day_summary(&self, date: NaiveDate, work: Duration) -> DaySummary {
+        let tld = &self.date2logday[&date];
+        let loggable = tld.loggable_time(self.daily_loggable);
+        // Leave logged on the day counts toward it the same way as in the week and month
+        let leave = self.day_logged_time_where(tld, |x| {
+            x != TimeLogEntryType::Work && x.counts_toward_target()
+        });
+
+        DaySummary {
+            worked: work + tld.logged_time(TimeLogEntryType::Overtime, self.daily_loggable),
+            loggable,
+            remaining: loggable - work - leave,
+            open_since: tld.open_entry_start(),
+        }
+    }
+
+    gen_time_logged_in_timeperiod_with!(
+        time_logged_in_week_of_with,
+        get_monday_in_week_of,
+        get_sunday_in_week_of
+    );
+    gen_time_logged_in_timeperiod_with!(
+        time_logged_in_month_of_with,
+        get_first_day_in_month_of,
+        get_last_day_in_month_of
+    );
+
+    gen_period_summary_with!(
+        week_summary_with,
+        compute_loggable_time_in_week_of,
+        time_logged_in_week_of_with,
+        get_monday_in_week_of,
+        get_sunday_in_week_of
+    );
+    gen_period_summary_with!(
+        month_summary_with,
+        compute_loggable_time_in_month_of,
+        time_logged_in_month_of_with,
+        get_first_day_in_month_of,
+        get_last_day_in_month_of
+    );
+
+    // Whether the file has been changed by someone else since it was loaded or saved
+    pub fn on_disk_differs(&self) -> io::Result<bool> {
+        match self.disk_hash.get() {
+            None => Ok(false),
+            Some(hash) => match hash_file(self.file_path.as_path()) {
+                Ok(x) => Ok(x != hash),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    // Refuses to replace a logfile that has entries with an empty log, as that is more likely
+    // to be a bug than intended unless the entries were deleted. See save_allow_empty.
+    pub fn save(&self) -> TimeLogResult<()> {
+        let empty = self.date2logday.values().all(TimeLogDay::is_empty);
+        let intended = self.allow_empty || self.entries_deleted;
+        if empty && !intended && self.flex_resets.is_empty() && self.file_has_entries() {
+            return Err(TimeLogError::inv_inp(
+                "Refusing to overwrite the logfile with an empty log, use `clear` to remove all entries or --force to save it anyway",
+            ));
+        }
+
+        self.save_allow_empty()
+    }
+
+    // Refuses to clobber changes made to the file after it was loaded
+    pub fn save_allow_empty(&self) -> TimeLogResult<()> {
+        if !self.overwrite_changes && self.on_disk_differs()? {
+            return Err(TimeLogError::inv_inp(
+                "The logfile was modified after it was loaded, not overwriting it (use --overwrite to save anyway)",
+            ));
+        }
+
+        self.overwrite()
+    }
+
+    // The logfile as it currently is on disk, which may differ from what save would write
+    pub fn read_on_disk(&self) -> TimeLogResult<String> {
+        Ok(fs::read_to_string(&self.file_path)?)
+    }
+
+    fn file_has_entries(&self) -> bool {
+        fs::read_to_string(&self.file_path)
+            .map(|s| {
+                s.lines()
+                    .map(str::trim)
+                    .any(|l| !l.is_empty() && !l.starts_with('#'))
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn overwrite(&self) -> TimeLogResult<()> {
+        if self.file_path.as_os_str().is_empty() {
+            return Err(TimeLogError::inv_inp(
+                "Can't save a log that was not loaded from a file",
+            ));
+        }
+
+        let fp: &Path = self.file_path.as_path();
+        debug_assert!(fp.exists(), "logfile does not exist");
+        // Neither backed up nor written when nothing changed, so that commands that only read
+        // the log don't rotate the real backups out
+        if fs::read(fp).is_ok_and(|on_disk| on_disk == self.write_entries().as_bytes()) {
+            self.disk_hash.set(Some(hash_file(fp)?));
+            return Ok(());
+        }
+
+        let bkp = self.backup_path(&backup_timestamp());
+        let bkp_fp = bkp.as_path();
+        fs::copy(fp, bkp_fp)?;
+        let file = File::create(fp)?;
+        match self.write_entries_to(BufWriter::new(file)) {
+            Ok(_) => {
+                self.disk_hash.set(Some(hash_file(fp)?));
+                self.prune_backups()?;
+                Ok(())
+            }
+            Err(e) => {
+                fs::copy(bkp_fp, fp)?;
+                fs::remove_file(bkp_fp)?;
+                let msg = format!("Failed to write to file (restoring backup): {}", e);
+                Err(match e {
+                    TimeLogError::IOError(ref err) => TimeLogError::io_error_extra_msg(err, &msg),
+                    _ => TimeLogError::other_io(&msg),
+                })
+            }
+        }
+    }
+
+    fn history_path(&self) -> PathBuf {
+        let mut name = self.file_path.file_name().unwrap_or_default().to_owned();
+        name.push(".history");
+        self.file_path.with_file_name(name)
+    }
+
+    // Appends a timestamped line to the history kept next to the logfile
+    pub fn record_change(&self, summary: &str) -> TimeLogResult<()> {
+        if self.file_path.as_os_str().is_empty() {
+            return Err(TimeLogError::inv_inp(
+                "Can't record changes to a log that was not loaded from a file",
+            ));
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.history_path())?;
+        writeln!(
+            file,
+            "{} | {}",
+            Local::now().format("%Y/%m/%d %H:%M:%S"),
+            summary
+        )?;
+        Ok(())
+    }
+
+    // Empty if nothing has been recorded yet
+    pub fn history(&self) -> TimeLogResult<String> {
+        match fs::read_to_string(self.history_path()) {
+            Ok(s) => Ok(s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn backup_path(&self, timestamp: &str) -> PathBuf {
+        let mut name = self.file_path.file_name().unwrap_or_default().to_owned();
+        name.push(format!(".bkp.{}", timestamp));
+        self.file_path.with_file_name(name)
+    }
+
+    // Oldest first. The timestamp in the name sorts in the order the backups were made.
+    pub fn list_backups(&self) -> io::Result<Vec<PathBuf>> {
+        self.list_timestamped(self.backup_path(""))
+    }
+
+    // The files next to the logfile named like `prefix` followed by a timestamp, oldest first
+    fn list_timestamped(&self, prefix: PathBuf) -> io::Result<Vec<PathBuf>> {
+        let prefix = prefix.file_name().unwrap_or_default().to_string_lossy();
+        let dir = match self.file_path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with(prefix.as_ref()))
+            {
+                backups.push(path);
+            }
+        }
+        backups.sort();
+
+        Ok(backups)
+    }
+
+    fn prune_backups(&self) -> io::Result<()> {
+        prune_oldest(&self.list_backups()?, self.max_backups)
+    }
+
+    // Not a backup, so that it is never restored by undo
+    fn undone_path(&self, timestamp: &str) -> PathBuf {
+        let mut name = self.file_path.file_name().unwrap_or_default().to_owned();
+        name.push(format!(".undone.{}", timestamp));
+        self.file_path.with_file_name(name)
+    }
+
+    // Replaces the logfile with the newest backup, which is then removed so that the
+    // next undo goes further back. The replaced logfile is kept, as it may have been edited
+    // by hand since it was last saved. Returns the restored backup and the kept logfile.
+    pub fn undo(&self) -> TimeLogResult<(PathBuf, PathBuf)> {
+        let newest = self
+            .list_backups()?
+            .pop()
+            .ok_or_else(|| TimeLogError::inv_inp("There are no backups to restore"))?;
+        let undone = self.undone_path(&backup_timestamp());
+        fs::copy(&self.file_path, &undone)?;
+        fs::copy(&newest, &self.file_path)?;
+        fs::remove_file(&newest)?;
+        self.disk_hash.set(Some(hash_file(&self.file_path)?));
+        // As many are kept as there are backups
+        prune_oldest(
+            &self.list_timestamped(self.undone_path(""))?,
+            self.max_backups,
+        )?;
+
+        Ok((newest, undone))
+    }
+
+    pub fn day(&self, date: NaiveDate) -> Option<&TimeLogDay> {
+        self.date2logday.get(&date)
+    }
+
+    pub fn days(&self) -> Vec<&TimeLogDay> {
+        let mut keys: Vec<&NaiveDate> = self.date2logday.keys().collect();
+        keys.sort();
+        keys.iter().map(|k| &self.date2logday[k]).collect()
+    }
+
+    // Sorted by date
+    pub fn diff<'a>(&'a self, other: &'a TimeLogger) -> Vec<DayDiff<'a>> {
+        let mut dates: Vec<&NaiveDate> = self
+            .date2logday
+            .keys()
+            .chain(other.date2logday.keys())
+            .collect();
+        dates.sort();
+        dates.dedup();
+
+        dates
+            .into_iter()
+            .filter_map(
+                |date| match (self.date2logday.get(date), other.date2logday.get(date)) {
+                    (Some(a), Some(b)) if a == b => None,
+                    (Some(a), Some(b)) => Some(DayDiff::Changed(a, b)),
+                    (Some(a), None) => Some(DayDiff::OnlyInSelf(a)),
+                    (None, Some(b)) => Some(DayDiff::OnlyInOther(b)),
+                    (None, None) => unreachable!("date comes from one of the logs"),
+                },
+            )
+            .collect()
+    }
+
+    pub fn get_latest_n_entries(&self, n: usize) -> Vec<&TimeLogDay> {
+        let mut days = Vec::with_capacity(n);
+        let mut keys: Vec<&NaiveDate> = self.date2logday.keys().collect();
+        keys.sort();
+
+        for (i, k) in keys.iter().rev().enumerate() {
+            if i >= n {
+                break;
+            }
+            days.push(&self.date2logday[k]);
+        }
+
+        days.reverse();
+
+        days
+    }
+
+    // A Work entry without times logs nothing, so batched Work days get a full-length
+    // interval instead: `work_hours` if given, otherwise 08:00 - 16:00.
+    // With `hours_per_day`, every batched day is an interval of that length from the work start.
+    pub fn batch_add(
+        &mut self,
+        ty: TimeLogEntryType,
+        from: NaiveDate,
+        to: NaiveDate,
+        opts: &BatchOptions,
+    ) -> TimeLogResult<BatchCounts> {
+        if from > to {
+            return Err(TimeLogError::inv_inp("'from' is after 'to'"));
+        }
+        let (start, end) = opts.work_hours.unwrap_or_else(default_work_hours);
+        if start >= end {
+            return Err(TimeLogError::inv_inp(
+                format!("Work start {} is not before work end {}", start, end).as_str(),
+            ));
+        }
+        let day_end = match opts
+            .hours_per_day
+            .map(|d| (d, start.overflowing_add_signed(d)))
+        {
+            None => None,
+            Some((d, (end, 0))) if d > Duration::zero() => Some(end),
+            Some(_) => {
+                return Err(TimeLogError::inv_inp(
+                    "Hours per day must be positive and end before midnight",
+                ))
+            }
+        };
+        let mut counts = BatchCounts {
+            added: 0,
+            skipped: 0,
+        };
+        let mut cur = from;
+        while cur < to {
+            if opts.weekday_only && !is_weekday(cur) {
+                cur = cur.succ();
+                continue;
+            }
+
+            if let Some(existing) = self.date2logday.get(&cur) {
+                if !opts.skip_existing {
+                    return Err(TimeLogError::inv_inp(
+                        format!(
+                            "There is already an entry for {}: {}",
+                            cur,
+                            existing.entries()[0]
+                        )
+                        .as_str(),
+                    ));
+                }
+                counts.skipped += 1;
+            } else {
+                let tld = match (ty, day_end) {
+                    (_, Some(day_end)) => TimeLogDay::full_with_interval(cur, ty, start, day_end),
+                    (TimeLogEntryType::Work, None) => {
+                        TimeLogDay::full_work_with_defaults(cur, start, end)
+                    }
+                    _ => TimeLogDay::full(cur, ty),
+                };
+                self.merge_day(tld)?;
+                counts.added += 1;
+            }
+
+            cur = cur.succ();
+        }
+
+        Ok(counts)
     }
 
     gen_verify_entries!(
@@ -412,53 +1576,435 @@ impl TimeLogger {
     );
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::Duration;
-    use chrono::NaiveTime;
+// Parses a log that isn't backed by a file, so `save` on it fails
+impl FromStr for TimeLogger {
+    type Err = TimeLogError;
+
+    fn from_str(s: &str) -> TimeLogResult<TimeLogger> {
+        let mut tl = TimeLogger::new(PathBuf::new());
+        tl.read_entries(s)?;
+        Ok(tl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use chrono::Duration;
+    use chrono::NaiveTime;
+
+    #[test]
+    fn timelogger_read_entries() {
+        let mon_1 = "2017/12/18 Mon | Work 06:31:00 07:00:00";
+        let mon_2 = "2017/12/18 Mon | Work 07:31:00 UNDEF";
+        let tue_1 = "2017/12/19 Tue | Work 07:31:00 11:50:00";
+        let tue_2 = "2017/12/19 Tue | Work 12:34:00 18:15:00";
+        let wed_1 = "2017/12/20 Wed | Work 09:10:00 11:55:00";
+        let wed_2 = "2017/12/20 Wed | Work 12:40:00 18:45:00";
+
+        let s = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
+        );
+
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = &NaiveDate::from_ymd(2017, 12, 18);
+        let tue = &NaiveDate::from_ymd(2017, 12, 19);
+        let wed = &NaiveDate::from_ymd(2017, 12, 20);
+
+        let mon_tld = format!("{}\n{}", mon_1, mon_2)
+            .as_str()
+            .parse::<TimeLogDay>()
+            .unwrap();
+        let tue_tld = format!("{}\n{}", tue_1, tue_2)
+            .as_str()
+            .parse::<TimeLogDay>()
+            .unwrap();
+        let wed_tld = format!("{}\n{}", wed_1, wed_2)
+            .as_str()
+            .parse::<TimeLogDay>()
+            .unwrap();
+
+        assert_eq!(logger.date2logday.len(), 3);
+        assert_eq!(logger.date2logday[mon], mon_tld);
+        assert_eq!(logger.date2logday[tue], tue_tld);
+        assert_eq!(logger.date2logday[wed], wed_tld);
+    }
+
+    #[test]
+    fn timelogger_write_entries_to() {
+        let s = "# timelog: utc
+# my log
+2017/12/18 Mon | FLEX RESET
+2017/12/18 Mon | Work 08:00:00 12:00:00
+2017/12/18 Mon | Work 13:00:00 UNDEF
+2017/12/19 Tue | Vacation UNDEF UNDEF
+";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mut streamed = Vec::new();
+        logger
+            .write_entries_to(BufWriter::new(&mut streamed))
+            .unwrap();
+        assert_eq!(streamed, s.as_bytes());
+        assert_eq!(streamed, logger.write_entries().as_bytes());
+    }
+
+    #[test]
+    fn timelogger_project_period() {
+        let s = "2017/12/18 Mon | Work 08:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 16:00:00\n\
+                 2017/12/20 Wed | Work 08:00:00 10:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        let wed = NaiveDate::from_ymd(2017, 12, 20);
+
+        // 17h in the two days before Wed, so Thu and Fri at 8.5h each make 19h + 17h of the 40h.
+        // The 2h so far on Wed count, but not toward the average.
+        assert_eq!(
+            logger.project_period(wed, Grouping::Week),
+            Duration::hours(19 + 17 - 40)
+        );
+        // Wed is already logged, so only Thu and Fri are projected at Mon's 9h
+        assert_eq!(
+            logger.project_period(wed.pred(), Grouping::Week),
+            Duration::hours(19 + 18 - 40)
+        );
+
+        // Weekend work counts toward the week but not toward the average
+        let s = "2017/12/18 Mon | Work 08:00:00 16:00:00\n\
+                 2017/12/23 Sat | Work 08:00:00 12:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(
+            logger.project_period(NaiveDate::from_ymd(2017, 12, 19), Grouping::Week),
+            Duration::hours(12 + 3 * 8 - 40)
+        );
+
+        // Days that are already logged, or have nothing to log, aren't projected
+        let s = "2017/12/25 Mon | Holiday UNDEF UNDEF\n\
+                 2017/12/26 Tue | Work 08:00:00 14:00:00\n\
+                 2017/12/27 Wed | Work 08:00:00 15:00:00\n\
+                 2017/12/29 Fri | Vacation UNDEF UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(
+            logger.project_period(NaiveDate::from_ymd(2017, 12, 27), Grouping::Week),
+            Duration::hours(6 + 7 + 6 + 8 - 32)
+        );
+        assert_eq!(
+            logger.project_period(NaiveDate::from_ymd(2017, 12, 25), Grouping::Week),
+            Duration::hours(6 + 7 + 8 - 32)
+        );
+    }
+
+    #[test]
+    fn timelogger_logical_today() {
+        let jan2 = NaiveDate::from_ymd(2018, 1, 2);
+        let boundary = Some(NaiveTime::from_hms(3, 0, 0));
+        assert_eq!(logical_today(jan2.and_hms(1, 30, 0), boundary), jan2.pred());
+        assert_eq!(logical_today(jan2.and_hms(3, 0, 0), boundary), jan2);
+        assert_eq!(logical_today(jan2.and_hms(1, 30, 0), None), jan2);
+
+        // The date comes from when the command is run, not from the time that is logged
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger.set_day_boundary(boundary);
+        assert_eq!(
+            logger.log_datetime(jan2.and_hms(1, 30, 0), NaiveTime::from_hms(1, 30, 0)),
+            jan2.and_hms(1, 30, 0)
+        );
+        assert_eq!(
+            logger.log_datetime(jan2.and_hms(1, 30, 0), NaiveTime::from_hms(23, 45, 0)),
+            jan2.pred().and_hms(23, 45, 0)
+        );
+        assert_eq!(
+            logger.log_datetime(jan2.and_hms(10, 0, 0), NaiveTime::from_hms(1, 30, 0)),
+            jan2.and_hms(1, 30, 0)
+        );
+
+        let ten_pm = NaiveTime::from_hms(22, 0, 0);
+        assert!(logger.ends_past_midnight(ten_pm, NaiveTime::from_hms(2, 0, 0)));
+        assert!(!logger.ends_past_midnight(ten_pm, NaiveTime::from_hms(21, 0, 0)));
+        assert!(!TimeLogger::new(PathBuf::new())
+            .ends_past_midnight(ten_pm, NaiveTime::from_hms(2, 0, 0)));
+    }
+
+    #[test]
+    fn timelogger_log_end_past_midnight() {
+        let jan1 = NaiveDate::from_ymd(2018, 1, 1);
+        let jan2 = jan1.succ();
+        let mut logger: TimeLogger = "2018/01/01 Mon | Work 22:00:00 UNDEF".parse().unwrap();
+        logger.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        let two_am = NaiveTime::from_hms(2, 0, 30);
+
+        assert_eq!(
+            logger.open_entry_before(jan2.and_time(two_am)),
+            Some((jan1, NaiveTime::from_hms(22, 0, 0)))
+        );
+        assert_eq!(logger.open_entry_before(jan2.and_hms(4, 0, 0)), None);
+        assert_eq!(
+            logger.open_entry_duration(jan2.and_time(two_am)).unwrap(),
+            (
+                NaiveTime::from_hms(22, 0, 0),
+                Duration::seconds(4 * 3600 + 30)
+            )
+        );
+        let entry = logger.log_end_past_midnight(jan2, two_am);
+        assert_eq!(entry.end(), Some(NaiveTime::from_hms(2, 0, 0)));
+        assert_eq!(
+            logger.day(jan1).unwrap().to_string(),
+            "2018/01/01 Mon | Work 22:00:00 00:00:00"
+        );
+        assert_eq!(
+            logger.day(jan2).unwrap().to_string(),
+            "2018/01/02 Tue | Work 00:00:00 02:00:00"
+        );
+        assert!(logger.day(jan1).unwrap().validate().is_ok());
+        assert_eq!(
+            logger.compute_logged_time_between(jan1, jan1, TimeLogEntryType::Work),
+            Duration::hours(2)
+        );
+    }
+
+    #[test]
+    fn timelogger_merge_day() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let day: TimeLogDay = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                               2017/12/18 Mon | Work 13:00:00 16:00:00"
+            .parse()
+            .unwrap();
+        assert_eq!(logger.merge_day(day.clone()), Ok(DayMerge::Merged));
+        assert_eq!(logger.day(day.date()), Some(&day));
+        assert_eq!(logger.merge_day(day.clone()), Ok(DayMerge::Merged));
+        assert_eq!(logger.day(day.date()), Some(&day));
+
+        let overlapping: TimeLogDay = "2017/12/18 Mon | Work 17:00:00 18:00:00\n\
+                                       2017/12/18 Mon | Work 15:00:00 17:00:00"
+            .parse()
+            .unwrap();
+        assert!(logger.merge_day(overlapping).is_err());
+        assert_eq!(logger.day(day.date()), Some(&day));
+
+        let tue: TimeLogDay = "2017/12/19 Tue | Vacation UNDEF UNDEF".parse().unwrap();
+        assert_eq!(logger.merge_day(tue.clone()), Ok(DayMerge::Created));
+        assert_eq!(logger.day(tue.date()), Some(&tue));
+    }
+
+    #[test]
+    fn timelogger_delete_entries_where() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Vacation 12:00:00 14:00:00\n\
+                 2017/12/18 Mon | Work 14:00:00 16:00:00\n\
+                 2017/12/18 Mon | Vacation 16:00:00 17:00:00\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        let is_vacation = |e: &TimeLogEntry| e.ty() == TimeLogEntryType::Vacation;
+        assert_eq!(logger.delete_entries_where(mon, is_vacation), 2);
+        assert_eq!(logger.delete_entries_where(mon, is_vacation), 0);
+        assert_eq!(
+            logger.day(mon).unwrap().to_string(),
+            "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+             2017/12/18 Mon | Work 14:00:00 16:00:00"
+        );
+
+        assert_eq!(logger.delete_entries_where(tue, is_vacation), 1);
+        assert!(logger.day(tue).is_none());
+        assert_eq!(logger.delete_entries_where(tue, is_vacation), 0);
+    }
+
+    #[test]
+    fn timelogger_delete_entry() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work 13:00:00 16:00:00\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        assert_eq!(logger.delete_entry(tue, 1), None);
+        assert_eq!(
+            logger.delete_entry(tue, 0).map(|e| e.ty()),
+            Some(TimeLogEntryType::Vacation)
+        );
+        assert!(!logger.date2logday.contains_key(&tue));
+        assert_eq!(logger.delete_entry(tue, 0), None);
+
+        assert!(logger.delete_entry(mon, 0).is_some());
+        assert_eq!(
+            logger.write_entries(),
+            "2017/12/18 Mon | Work 13:00:00 16:00:00\n"
+        );
+
+        logger.date2logday.insert(tue, TimeLogDay::empty(tue));
+        assert_eq!(
+            logger.write_entries(),
+            "2017/12/18 Mon | Work 13:00:00 16:00:00\n"
+        );
+    }
 
     #[test]
-    fn timelogger_read_entries() {
-        let mon_1 = "2017/12/18 Mon | Work 06:31:00 07:00:00";
-        let mon_2 = "2017/12/18 Mon | Work 07:31:00 UNDEF";
-        let tue_1 = "2017/12/19 Tue | Work 07:31:00 11:50:00";
-        let tue_2 = "2017/12/19 Tue | Work 12:34:00 18:15:00";
-        let wed_1 = "2017/12/20 Wed | Work 09:10:00 11:55:00";
-        let wed_2 = "2017/12/20 Wed | Work 12:40:00 18:45:00";
+    fn timelogger_on_disk_differs() {
+        let path =
+            std::env::temp_dir().join(format!("timelog_on_disk_differs_{}", std::process::id()));
+        fs::write(&path, "2017/12/18 Mon | Work 08:00:00 12:00:00\n").unwrap();
+        let mut logger = TimeLogger::from_file(path.clone()).unwrap();
+        logger.set_max_backups(0);
+        assert!(!logger.on_disk_differs().unwrap());
+
+        logger.log_start(
+            NaiveDate::from_ymd(2017, 12, 19),
+            NaiveTime::from_hms(8, 0, 0),
+        );
+        logger.save().unwrap();
+        assert!(!logger.on_disk_differs().unwrap());
 
-        let s = format!(
-            "{}\n{}\n{}\n{}\n{}\n{}",
-            mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
+        let edited = "2017/12/18 Mon | Work 08:00:00 13:00:00\n";
+        fs::write(&path, edited).unwrap();
+        assert!(logger.on_disk_differs().unwrap());
+        assert!(logger.save().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+
+        logger.set_overwrite_changes(true);
+        logger.save().unwrap();
+        assert!(!logger.on_disk_differs().unwrap());
+        assert_ne!(fs::read_to_string(&path).unwrap(), edited);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn timelogger_rotating_backups() {
+        let dir =
+            std::env::temp_dir().join(format!("timelog_rotating_backups_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log");
+        let mut logger = TimeLogger::from_file(path.clone()).unwrap();
+        logger.set_max_backups(5);
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let mut newest = Vec::new();
+        for day in 0..7 {
+            logger.log_start(mon + Duration::days(day), NaiveTime::from_hms(8, 0, 0));
+            logger.save().unwrap();
+            newest.push(logger.list_backups().unwrap().pop().unwrap());
+        }
+        assert_eq!(logger.list_backups().unwrap(), newest[2..]);
+
+        // Saving without changes neither writes nor rotates the backups
+        logger.save().unwrap();
+        assert_eq!(logger.list_backups().unwrap(), newest[2..]);
+
+        // The newest backup is the log as it was before the last save. A hand edit made
+        // since then is kept next to the logfile.
+        let edited = fs::read_to_string(&path).unwrap() + "# Edited by hand\n";
+        fs::write(&path, &edited).unwrap();
+        let before_last = fs::read_to_string(&newest[6]).unwrap();
+        let (restored, undone) = logger.undo().unwrap();
+        assert_eq!(restored, newest[6]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), before_last);
+        assert_eq!(fs::read_to_string(&undone).unwrap(), edited);
+        assert_eq!(logger.list_backups().unwrap(), newest[2..6]);
+
+        // The kept logfiles are pruned like the backups
+        logger.set_max_backups(2);
+        let mut kept = vec![undone];
+        for _ in 0..3 {
+            kept.push(logger.undo().unwrap().1);
+        }
+        assert_eq!(
+            logger.list_timestamped(logger.undone_path("")).unwrap(),
+            kept[2..]
         );
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        let mon = &NaiveDate::from_ymd(2017, 12, 18);
-        let tue = &NaiveDate::from_ymd(2017, 12, 19);
-        let wed = &NaiveDate::from_ymd(2017, 12, 20);
+    #[test]
+    fn timelogger_clear() {
+        let dir = std::env::temp_dir().join(format!("timelog_clear_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log");
+        let s = "2017/12/18 Mon | FLEX RESET\n2017/12/18 Mon | Work 08:00:00 12:00:00\n";
+        fs::write(&path, s).unwrap();
+
+        let mut logger = TimeLogger::from_file(path.clone()).unwrap();
+        logger.clear();
+        assert!(logger.is_empty());
+        assert!(matches!(
+            logger.save(),
+            Err(TimeLogError::InvalidInputError(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), s);
+
+        // Saving over changes on disk doesn't lift the guard
+        logger.set_overwrite_changes(true);
+        assert!(logger.save().is_err());
+        logger.set_overwrite_changes(false);
+        assert!(logger.list_backups().unwrap().is_empty());
+
+        logger.save_allow_empty().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        let backups = logger.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), s);
+
+        // An empty log may replace an empty logfile
+        logger.save().unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        let mon_tld = format!("{}\n{}", mon_1, mon_2)
-            .as_str()
-            .parse::<TimeLogDay>()
-            .unwrap();
-        let tue_tld = format!("{}\n{}", tue_1, tue_2)
-            .as_str()
-            .parse::<TimeLogDay>()
-            .unwrap();
-        let wed_tld = format!("{}\n{}", wed_1, wed_2)
-            .as_str()
-            .parse::<TimeLogDay>()
-            .unwrap();
+    #[test]
+    fn timelogger_save_empty() {
+        let dir = std::env::temp_dir().join(format!("timelog_save_empty_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log");
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n";
+        fs::write(&path, s).unwrap();
+        let date = NaiveDate::from_ymd(2017, 12, 18);
+
+        // Deleting the last entry is intended
+        let mut logger = TimeLogger::from_file(path.clone()).unwrap();
+        assert!(logger.delete_entry(date, 0).is_some());
+        logger.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        // Ending up empty without deleting anything is refused unless forced
+        fs::write(&path, s).unwrap();
+        let mut logger = TimeLogger::from_file(path.clone()).unwrap();
+        logger.date2logday.clear();
+        let err = logger.save().unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), s);
+        logger.set_allow_empty(true);
+        logger.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        assert_eq!(logger.date2logday.len(), 3);
-        assert_eq!(logger.date2logday[mon], mon_tld);
-        assert_eq!(logger.date2logday[tue], tue_tld);
-        assert_eq!(logger.date2logday[wed], wed_tld);
+    #[test]
+    fn timelogger_clone() {
+        let s = "2017/12/18 Mon | Work 06:31:00 07:00:00\n2017/12/19 Tue | Work 07:31:00 UNDEF";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mut cloned = logger.clone();
+        assert_eq!(cloned.date2logday, logger.date2logday);
+        assert_eq!(cloned.write_entries(), logger.write_entries());
+
+        cloned.log_end(
+            NaiveDate::from_ymd(2017, 12, 19),
+            NaiveTime::from_hms(16, 0, 0),
+        );
+        assert_ne!(cloned.date2logday, logger.date2logday);
     }
 
     #[test]
@@ -475,11 +2021,7 @@ mod tests {
             mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
         );
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let logger: TimeLogger = s.parse().unwrap();
 
         let mon = NaiveDate::from_ymd(2017, 12, 18);
         let tue = NaiveDate::from_ymd(2017, 12, 19);
@@ -527,11 +2069,7 @@ mod tests {
             mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
         );
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let logger: TimeLogger = s.parse().unwrap();
 
         let prev_fri = NaiveDate::from_ymd(2017, 12, 15);
         let sat = NaiveDate::from_ymd(2017, 12, 16);
@@ -673,11 +2211,7 @@ mod tests {
             nov_mon_1, nov_tue_1, nov_wed_1, mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
         );
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let logger: TimeLogger = s.parse().unwrap();
 
         let prev_fri = NaiveDate::from_ymd(2017, 12, 15);
         let sat = NaiveDate::from_ymd(2017, 12, 16);
@@ -701,83 +2235,514 @@ mod tests {
             d40hr
         );
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(sun, TimeLogEntryType::Work),
-            d40hr
+            logger.compute_loggable_time_in_week_of(sun, TimeLogEntryType::Work),
+            d40hr
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_week_of(mon, TimeLogEntryType::Work),
+            d40hr
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_week_of(tue, TimeLogEntryType::Work),
+            d40hr
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_week_of(wed, TimeLogEntryType::Work),
+            d40hr
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_week_of(thu, TimeLogEntryType::Work),
+            d40hr
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_week_of(fri, TimeLogEntryType::Work),
+            d40hr
+        );
+
+        assert_eq!(
+            logger.compute_loggable_time_in_month_of(sun, TimeLogEntryType::Work),
+            Duration::hours(168)
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_month_of(mon, TimeLogEntryType::Work),
+            Duration::hours(168)
+        );
+        assert_eq!(
+            logger.compute_loggable_time_in_month_of(nov_mon, TimeLogEntryType::Work),
+            Duration::hours(176)
+        );
+    }
+
+    #[test]
+    fn timelogger_month_bounds_leap_year() {
+        assert_eq!(
+            get_last_day_in_month_of(NaiveDate::from_ymd(2020, 2, 10)),
+            NaiveDate::from_ymd(2020, 2, 29)
+        );
+        assert_eq!(
+            get_last_day_in_month_of(NaiveDate::from_ymd(2021, 2, 10)),
+            NaiveDate::from_ymd(2021, 2, 28)
+        );
+        assert_eq!(
+            get_last_day_in_month_of(NaiveDate::from_ymd(2017, 12, 18)),
+            NaiveDate::from_ymd(2017, 12, 31)
+        );
+
+        let s = "2020/02/28 Fri | Work 08:00:00 16:00:00\n\
+                 2020/02/29 Sat | Work 10:00:00 12:00:00\n\
+                 2021/02/26 Fri | Work 08:00:00 16:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let feb_2020 = NaiveDate::from_ymd(2020, 2, 10);
+        let feb_2021 = NaiveDate::from_ymd(2021, 2, 10);
+        assert_eq!(
+            logger.time_logged_in_month_of_with(feb_2020, None),
+            Ok(Duration::hours(10))
+        );
+        assert_eq!(
+            logger.time_logged_in_month_of_with(feb_2021, None),
+            Ok(Duration::hours(8))
+        );
+    }
+
+    #[test]
+    fn timelogger_loggable_time_in_leap_february() {
+        let logger = TimeLogger::new(PathBuf::new());
+
+        // 2024/02/29 is a Thursday and should be a loggable workday
+        assert_eq!(
+            logger.compute_loggable_time_in_month_of(
+                NaiveDate::from_ymd(2024, 2, 1),
+                TimeLogEntryType::Work
+            ),
+            Duration::hours(21 * 8)
+        );
+        // 2020/02/29 is a Saturday
+        assert_eq!(
+            logger.compute_loggable_time_in_month_of(
+                NaiveDate::from_ymd(2020, 2, 1),
+                TimeLogEntryType::Work
+            ),
+            Duration::hours(20 * 8)
+        );
+    }
+
+    #[test]
+    fn timelogger_log_start_end() {
+        let mut logger = TimeLogger::new(PathBuf::new());
+        let today = NaiveDate::from_ymd(2018, 01, 01);
+        let start = NaiveTime::from_hms(12, 0, 0);
+        let end = NaiveTime::from_hms(13, 0, 0);
+        let started = logger.log_start(today, start);
+        assert_eq!(started.start(), Some(start));
+        let ended = logger.log_end(today, end);
+        assert_eq!(ended.start(), Some(start));
+        assert_eq!(ended.end(), Some(end));
+        let tld = "2018/01/01 Mon | Work 12:00:00 13:00:00"
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert_eq!(logger.date2logday[&today], tld);
+    }
+
+    #[test]
+    fn timelogger_open_entry_end_after() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n2017/12/18 Mon | Work 13:00:00 UNDEF";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let end = logger
+            .open_entry_end_after(mon, Duration::minutes(3 * 60 + 30))
+            .unwrap();
+        assert_eq!(end, NaiveTime::from_hms(16, 30, 0));
+        assert_eq!(
+            logger.time_logged_at_date_with(mon, Some(end)).unwrap(),
+            Duration::minutes(7 * 60 + 30)
+        );
+
+        assert!(logger
+            .open_entry_end_after(mon, Duration::hours(12))
+            .is_err());
+        assert!(logger
+            .open_entry_end_after(mon.succ(), Duration::hours(1))
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_open_entry_duration() {
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n2017/12/18 Mon | Work 13:00:00 UNDEF";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        assert_eq!(
+            logger.open_entry_duration(mon.and_hms(14, 15, 0)),
+            Ok((NaiveTime::from_hms(13, 0, 0), Duration::minutes(75)))
+        );
+        assert!(logger.open_entry_duration(mon.and_hms(12, 0, 0)).is_err());
+        assert!(logger
+            .open_entry_duration(mon.succ().and_hms(14, 0, 0))
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_flex_time() {
+        let days = [
+            "2017/12/11 Mon | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/12 Tue | Work 09:00:00 16:00:00\n", // 7
+            "2017/12/13 Wed | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/14 Thu | Work 10:00:00 17:00:00\n", // 7
+            "2017/12/15 Fri | Work 08:00:00 15:35:00\n", // 7;35
+            // => 37;35
+            "2017/12/18 Mon | Work 08:00:00 18:00:00\n", // 10
+            "2017/12/19 Tue | Work 10:00:00 18:25:00\n", // 8;25
+            "2017/12/20 Wed | Work 09:00:00 16:00:00\n", // 7
+            "2017/12/21 Thu | Work 10:00:00 17:00:00\n", // 7
+            "2017/12/22 Fri | Work 07:00:00 18:00:00\n", // 11
+            // => 43;25
+            "2017/12/25 Mon | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/26 Tue | Work 09:00:00 16:00:00\n", // 7
+            "2017/12/27 Wed | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/28 Thu | Work 10:00:00 18:00:00\n", // 8
+            "2017/12/29 Fri | Work 08:00:00 16:00:00\n", // 8
+            // => 39
+            "2018/01/01 Mon | Work 08:00:00 16:00:00\n",
+        ];
+
+        let mut s = String::new();
+        for d in days.iter() {
+            s.push_str(d);
+        }
+
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon1 = NaiveDate::from_ymd(2017, 12, 18);
+        let mon2 = NaiveDate::from_ymd(2017, 12, 25);
+        let mon3 = NaiveDate::from_ymd(2018, 01, 01);
+        let tue3 = NaiveDate::from_ymd(2018, 01, 02);
+
+        assert_eq!(logger.flextime_as_of(mon1), Duration::minutes(2 * 60 + 25));
+        assert_eq!(logger.flextime_as_of(mon2), -Duration::minutes(60));
+        assert_eq!(logger.flextime_as_of(mon3), Duration::minutes(0));
+        assert_eq!(logger.flextime_as_of(tue3), Duration::minutes(0));
+
+        assert_eq!(
+            logger.weekly_flex_series(),
+            vec![
+                (mon1.pred(), Duration::minutes(2 * 60 + 25)),
+                (mon2.pred(), -Duration::minutes(60)),
+                (mon3.pred(), Duration::minutes(0)),
+                (NaiveDate::from_ymd(2018, 1, 7), Duration::hours(32)),
+            ]
+        );
+        assert!(TimeLogger::new(PathBuf::new())
+            .weekly_flex_series()
+            .is_empty());
+
+        // The balance carried over between weeks agrees with recounting from the reset
+        let mut logger = logger;
+        logger.reset_flex(NaiveDate::from_ymd(2017, 12, 20));
+        let series = logger.weekly_flex_series();
+        assert_eq!(series.len(), 4);
+        for (sunday, flex) in series {
+            assert_eq!(flex, logger.flextime_as_of(sunday.succ()));
+        }
+    }
+
+    #[test]
+    fn timelogger_batch_add_work_with_defaults() {
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        let hours = (NaiveTime::from_hms(8, 0, 0), NaiveTime::from_hms(16, 0, 0));
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .batch_add(
+                TimeLogEntryType::Work,
+                mon,
+                tue,
+                &BatchOptions {
+                    work_hours: Some(hours),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            logger.compute_logged_time_between(mon, mon, TimeLogEntryType::Work),
+            Duration::hours(8)
+        );
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        let backwards = (hours.1, hours.0);
+        assert!(logger
+            .batch_add(
+                TimeLogEntryType::Work,
+                mon,
+                tue,
+                &BatchOptions {
+                    work_hours: Some(backwards),
+                    ..Default::default()
+                },
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_batch_add_work_week() {
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let next_mon = NaiveDate::from_ymd(2017, 12, 25);
+        let weekday_only = BatchOptions {
+            weekday_only: true,
+            ..Default::default()
+        };
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .batch_add(TimeLogEntryType::Work, mon, next_mon, &weekday_only)
+            .unwrap();
+        assert_eq!(
+            logger.time_logged_in_week_of_with(mon, None).unwrap(),
+            Duration::hours(40)
+        );
+        assert_eq!(logger.verify_entries_in_week_of(mon), None);
+    }
+
+    #[test]
+    fn timelogger_batch_add_weekdays_round_trip() {
+        // Crosses a month boundary
+        let from = NaiveDate::from_ymd(2018, 3, 20);
+        let to = NaiveDate::from_ymd(2018, 4, 10);
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .batch_add(
+                TimeLogEntryType::Vacation,
+                from,
+                to,
+                &BatchOptions::default(),
+            )
+            .unwrap();
+
+        let written = logger.write_entries();
+        assert_eq!(written.lines().count(), 21);
+        for line in written.lines() {
+            let (date, weekday) = line.split_once(" | ").unwrap().0.split_once(' ').unwrap();
+            let date = NaiveDate::parse_from_str(date, "%Y/%m/%d").unwrap();
+            assert_eq!(weekday, date.weekday().to_string());
+        }
+
+        let reread: TimeLogger = written.parse().unwrap();
+        assert_eq!(reread.write_entries(), written);
+    }
+
+    #[test]
+    fn timelogger_diff() {
+        let ours_s = "2017/12/18 Mon | Work 08:00:00 16:00:00
+2017/12/19 Tue | Work 08:00:00 16:00:00
+2017/12/20 Wed | Work 08:00:00 12:00:00";
+        let theirs_s = "2017/12/18 Mon | Work 08:00:00 16:00:00
+2017/12/19 Tue | Work 08:00:00 17:00:00
+2017/12/21 Thu | Vacation UNDEF UNDEF";
+
+        let ours: TimeLogger = ours_s.parse().unwrap();
+        let theirs: TimeLogger = theirs_s.parse().unwrap();
+
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        let wed = NaiveDate::from_ymd(2017, 12, 20);
+        let thu = NaiveDate::from_ymd(2017, 12, 21);
+        assert_eq!(
+            ours.diff(&theirs),
+            vec![
+                DayDiff::Changed(&ours.date2logday[&tue], &theirs.date2logday[&tue]),
+                DayDiff::OnlyInSelf(&ours.date2logday[&wed]),
+                DayDiff::OnlyInOther(&theirs.date2logday[&thu]),
+            ]
+        );
+        assert!(ours.diff(&ours).is_empty());
+    }
+
+    #[test]
+    fn timelogger_batch_add_skip_existing() {
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let fri = NaiveDate::from_ymd(2017, 12, 22);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        let s = "2017/12/20 Wed | Work 09:00:00 12:00:00\n";
+        let mut opts = BatchOptions {
+            weekday_only: true,
+            ..Default::default()
+        };
+
+        let mut logger: TimeLogger = s.parse().unwrap();
+        assert!(matches!(
+            logger.batch_add(TimeLogEntryType::Vacation, mon, fri, &opts),
+            Err(TimeLogError::InvalidInputError(m))
+                if m.ends_with(": 2017/12/20 Wed | Work 09:00:00 12:00:00")
+        ));
+
+        let mut logger: TimeLogger = s.parse().unwrap();
+        assert!(matches!(
+            logger.batch_add(TimeLogEntryType::Vacation, fri, mon, &opts),
+            Err(TimeLogError::InvalidInputError(_))
+        ));
+
+        opts.skip_existing = true;
+        assert_eq!(
+            logger.batch_add(TimeLogEntryType::Vacation, mon, fri, &opts),
+            Ok(BatchCounts {
+                added: 3,
+                skipped: 1
+            })
+        );
+        assert_eq!(
+            logger.compute_logged_time_between(mon, sun, TimeLogEntryType::Work),
+            Duration::hours(3)
+        );
+        assert_eq!(
+            logger.compute_logged_time_between(mon, sun, TimeLogEntryType::Vacation),
+            Duration::hours(3 * 8)
         );
+    }
+
+    #[test]
+    fn timelogger_batch_add_hours_per_day() {
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let next_mon = NaiveDate::from_ymd(2017, 12, 25);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        let leave = TimeLogEntryType::ParentalLeave;
+        let weekday_only = BatchOptions {
+            weekday_only: true,
+            ..Default::default()
+        };
+        let per_day = |d| BatchOptions {
+            weekday_only: true,
+            hours_per_day: Some(d),
+            ..Default::default()
+        };
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .batch_add(leave, mon, next_mon, &per_day(Duration::hours(6)))
+            .unwrap();
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(mon, TimeLogEntryType::Work),
-            d40hr
+            logger.compute_logged_time_between(mon, sun, leave),
+            Duration::hours(5 * 6)
         );
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .batch_add(leave, mon, next_mon, &weekday_only)
+            .unwrap();
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(tue, TimeLogEntryType::Work),
-            d40hr
+            logger.compute_logged_time_between(mon, sun, leave),
+            Duration::hours(5 * 8)
         );
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        assert!(logger
+            .batch_add(leave, mon, next_mon, &per_day(Duration::zero()))
+            .is_err());
+        assert!(logger
+            .batch_add(leave, mon, next_mon, &per_day(Duration::hours(20)))
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_stats() {
+        let s = "2017/12/18 Mon | Work 08:00:00 18:00:00
+2017/12/19 Tue | Work 10:00:00 18:25:00
+2017/12/20 Wed | Work 09:00:00 16:00:00
+2017/12/20 Wed | Work 17:00:00 UNDEF
+2017/12/21 Thu | Vacation UNDEF UNDEF
+2017/12/22 Fri | Work 07:00:00 11:00:00
+2017/12/25 Mon | Work 08:00:00 16:00:00";
+
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let fri = NaiveDate::from_ymd(2017, 12, 22);
+        let stats = logger.stats(mon, fri);
+        // The vacation day is not a workday
+        assert_eq!(stats.n_days, 4);
+        assert_eq!(stats.total, Duration::minutes(29 * 60 + 25));
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(wed, TimeLogEntryType::Work),
-            d40hr
+            stats.average(),
+            Duration::minutes(7 * 60 + 21) + Duration::seconds(15)
         );
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(thu, TimeLogEntryType::Work),
-            d40hr
+            logger.busiest_day(mon, fri),
+            Some((mon, Duration::hours(10)))
         );
         assert_eq!(
-            logger.compute_loggable_time_in_week_of(fri, TimeLogEntryType::Work),
-            d40hr
+            logger.quietest_day(mon, fri),
+            Some((fri, Duration::hours(4)))
         );
 
+        let next_mon = NaiveDate::from_ymd(2017, 12, 25);
         assert_eq!(
-            logger.compute_loggable_time_in_month_of(sun, TimeLogEntryType::Work),
-            Duration::hours(168)
+            logger.busiest_day(mon, next_mon),
+            Some((mon, Duration::hours(10)))
         );
         assert_eq!(
-            logger.compute_loggable_time_in_month_of(mon, TimeLogEntryType::Work),
-            Duration::hours(168)
+            logger.quietest_day(mon, next_mon),
+            Some((fri, Duration::hours(4)))
+        );
+
+        // Ties go to the earliest day
+        let tied: TimeLogger = "2017/12/18 Mon | Work 08:00:00 16:00:00\n\
+                                2017/12/19 Tue | Work 09:00:00 17:00:00\n"
+            .parse()
+            .unwrap();
+        assert_eq!(tied.busiest_day(mon, fri), Some((mon, Duration::hours(8))));
+        assert_eq!(tied.quietest_day(mon, fri), Some((mon, Duration::hours(8))));
+
+        let empty = logger.stats(
+            NaiveDate::from_ymd(2018, 1, 1),
+            NaiveDate::from_ymd(2018, 1, 7),
         );
+        assert_eq!(empty.n_days, 0);
+        assert_eq!(empty.average(), Duration::zero());
         assert_eq!(
-            logger.compute_loggable_time_in_month_of(nov_mon, TimeLogEntryType::Work),
-            Duration::hours(176)
+            logger.busiest_day(
+                NaiveDate::from_ymd(2018, 1, 1),
+                NaiveDate::from_ymd(2018, 1, 7)
+            ),
+            None
         );
-    }
 
-    #[test]
-    fn timelogger_log_start_end() {
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        let today = NaiveDate::from_ymd(2018, 01, 01);
-        let start = NaiveTime::from_hms(12, 0, 0);
-        let end = NaiveTime::from_hms(13, 0, 0);
-        logger.log_start(today, start);
-        logger.log_end(today, end);
-        let tld = "2018/01/01 Mon | Work 12:00:00 13:00:00"
-            .parse::<TimeLogDay>()
-            .unwrap();
-        assert_eq!(logger.date2logday[&today], tld);
+        let by_weekday = logger.worked_by_weekday(mon, NaiveDate::from_ymd(2017, 12, 25));
+        assert_eq!(by_weekday[0], Duration::hours(18));
+        assert_eq!(by_weekday[3], Duration::zero());
+        let stats = logger.stats(mon, NaiveDate::from_ymd(2017, 12, 25));
+        assert_eq!(stats.avg_by_weekday[0], Duration::hours(9));
+        assert_eq!(stats.avg_by_weekday[4], Duration::hours(4));
+
+        assert_eq!(
+            logger.date_range(),
+            Some((mon, NaiveDate::from_ymd(2017, 12, 25)))
+        );
     }
 
     #[test]
-    fn timelogger_flex_time() {
+    fn timelogger_flex_time_reset() {
         let days = [
+            "2017/12/04 Mon | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/05 Tue | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/06 Wed | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/07 Thu | Work 08:00:00 16:00:00\n", // 8
+            "2017/12/08 Fri | Work 08:00:00 15:00:00\n", // 7
+            // => 39
             "2017/12/11 Mon | Work 08:00:00 16:00:00\n", // 8
             "2017/12/12 Tue | Work 09:00:00 16:00:00\n", // 7
             "2017/12/13 Wed | Work 08:00:00 16:00:00\n", // 8
             "2017/12/14 Thu | Work 10:00:00 17:00:00\n", // 7
             "2017/12/15 Fri | Work 08:00:00 15:35:00\n", // 7;35
             // => 37;35
+            "2017/12/18 Mon | FLEX RESET\n",
             "2017/12/18 Mon | Work 08:00:00 18:00:00\n", // 10
             "2017/12/19 Tue | Work 10:00:00 18:25:00\n", // 8;25
             "2017/12/20 Wed | Work 09:00:00 16:00:00\n", // 7
             "2017/12/21 Thu | Work 10:00:00 17:00:00\n", // 7
             "2017/12/22 Fri | Work 07:00:00 18:00:00\n", // 11
             // => 43;25
-            "2017/12/25 Mon | Work 08:00:00 16:00:00\n", // 8
-            "2017/12/26 Tue | Work 09:00:00 16:00:00\n", // 7
-            "2017/12/27 Wed | Work 08:00:00 16:00:00\n", // 8
-            "2017/12/28 Thu | Work 10:00:00 18:00:00\n", // 8
-            "2017/12/29 Fri | Work 08:00:00 16:00:00\n", // 8
-            // => 39
-            "2018/01/01 Mon | Work 08:00:00 16:00:00\n",
+            "2017/12/25 Mon | Work 08:00:00 16:00:00\n",
         ];
 
         let mut s = String::new();
@@ -785,21 +2750,93 @@ mod tests {
             s.push_str(d);
         }
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let mut logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(logger.write_entries(), s);
 
+        let fri1 = NaiveDate::from_ymd(2017, 12, 15);
         let mon1 = NaiveDate::from_ymd(2017, 12, 18);
+        let wed1 = NaiveDate::from_ymd(2017, 12, 20);
         let mon2 = NaiveDate::from_ymd(2017, 12, 25);
-        let mon3 = NaiveDate::from_ymd(2018, 01, 01);
-        let tue3 = NaiveDate::from_ymd(2018, 01, 02);
 
-        assert_eq!(logger.flextime_as_of(mon1), Duration::minutes(2 * 60 + 25));
-        assert_eq!(logger.flextime_as_of(mon2), -Duration::minutes(60));
-        assert_eq!(logger.flextime_as_of(mon3), Duration::minutes(0));
-        assert_eq!(logger.flextime_as_of(tue3), Duration::minutes(0));
+        // Unaffected before the reset, where the first week is counted
+        assert_eq!(logger.flextime_as_of(fri1), Duration::hours(1));
+        // Only the week after the reset is accumulated
+        assert_eq!(logger.flextime_as_of(mon1), Duration::hours(0));
+        assert_eq!(logger.flextime_as_of(wed1), Duration::hours(0));
+        assert_eq!(logger.flextime_as_of(mon2), -Duration::minutes(3 * 60 + 25));
+
+        logger.reset_flex(mon2);
+        assert_eq!(logger.flextime_as_of(mon2), Duration::hours(0));
+        // 12/26 - 12/29 have no entries
+        assert_eq!(
+            logger.flextime_as_of(NaiveDate::from_ymd(2018, 1, 1)),
+            Duration::hours(32)
+        );
+        assert!(logger
+            .write_entries()
+            .ends_with("2017/12/25 Mon | FLEX RESET\n2017/12/25 Mon | Work 08:00:00 16:00:00\n"));
+    }
+
+    #[test]
+    fn timelogger_flex_time_holiday() {
+        let s = "2017/12/18 Mon | Work 08:00:00 16:00:00
+2017/12/25 Mon | Holiday UNDEF UNDEF
+2017/12/26 Tue | Work 08:00:00 16:00:00
+2017/12/27 Wed | Work 08:00:00 16:00:00
+2017/12/28 Thu | Work 08:00:00 16:00:00
+2017/12/29 Fri | Work 08:00:00 16:00:00";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 25);
+        let fri = NaiveDate::from_ymd(2017, 12, 29);
+        assert_eq!(
+            logger.compute_loggable_time_between(mon, mon, TimeLogEntryType::Work),
+            Duration::hours(0)
+        );
+        assert_eq!(
+            logger.compute_loggable_time_between(mon, fri, TimeLogEntryType::Work),
+            Duration::hours(32)
+        );
+        assert_eq!(
+            logger.time_logged_in_week_of_with(mon, None).unwrap(),
+            Duration::hours(32)
+        );
+
+        // Tue - Fri the week before have no entries
+        let before = logger.flextime_as_of(mon);
+        assert_eq!(before, Duration::hours(32));
+        assert_eq!(
+            logger.flextime_as_of(NaiveDate::from_ymd(2018, 1, 1)),
+            before
+        );
+    }
+
+    #[test]
+    fn timelogger_half_holiday() {
+        let s = "2017/12/18 Mon | Work 08:00:00 16:00:00
+2017/12/22 Fri | Holiday 08:00:00 12:00:00
+2017/12/22 Fri | Work 12:00:00 16:00:00";
+        let logger: TimeLogger = s.parse().unwrap();
+        let fri = NaiveDate::from_ymd(2017, 12, 22);
+
+        // The Holiday time counts toward the partly worked day, in the day and in the week
+        let day = logger.today_summary(fri, None).unwrap();
+        assert_eq!(day.worked, Duration::hours(4));
+        assert_eq!(day.loggable, Duration::hours(8));
+        assert_eq!(day.remaining, Duration::zero());
+        assert_eq!(
+            logger.time_logged_in_week_of_with(fri, None).unwrap(),
+            Duration::hours(16)
+        );
+        assert_eq!(
+            logger.grouped_totals(fri, fri, Grouping::Week),
+            vec![(fri, fri, Duration::hours(8))]
+        );
+        // Only Tue - Thu are missing
+        assert_eq!(
+            logger.flextime_as_of(NaiveDate::from_ymd(2017, 12, 25)),
+            Duration::hours(24)
+        );
     }
 
     #[test]
@@ -819,17 +2856,517 @@ mod tests {
             s.push_str(d);
         }
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let logger: TimeLogger = s.parse().unwrap();
 
         let mon = NaiveDate::from_ymd(2017, 12, 18);
 
         assert_eq!(logger.flextime_as_of(mon), -Duration::minutes(60 + 35));
     }
 
+    #[test]
+    fn timelogger_normalize_out_of_order() {
+        let s = "2017/12/19 Tue | Work 12:34:00 18:15:00\n\
+                 2017/12/18 Mon | Work 07:31:00 UNDEF\n\
+                 2017/12/19 Tue | Work 07:31:00 11:50:00\n\
+                 2017/12/18 Mon | Work 06:31:00 07:00:00\n";
+        let canonical = "2017/12/18 Mon | Work 06:31:00 07:00:00\n\
+                         2017/12/18 Mon | Work 07:31:00 UNDEF\n\
+                         2017/12/19 Tue | Work 07:31:00 11:50:00\n\
+                         2017/12/19 Tue | Work 12:34:00 18:15:00\n";
+
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(logger.date2logday.len(), 2);
+        assert_eq!(logger.write_entries(), canonical);
+    }
+
+    #[test]
+    fn timelogger_from_str() {
+        let s = "2017/12/18 Mon | Work 08:00:00 16:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(logger.write_entries(), s);
+        assert!(logger.save().is_err());
+
+        assert!("2017/12/18 Mon | Nap".parse::<TimeLogger>().is_err());
+    }
+
+    #[test]
+    fn timelogger_counts() {
+        let empty: TimeLogger = "".parse().unwrap();
+        assert_eq!(empty.days_count(), 0);
+        assert_eq!(empty.entries_count(), 0);
+
+        let s = "2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work 12:30:00 16:00:00\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n\
+                 2017/12/20 Wed | Work 08:00:00 UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(logger.days_count(), 3);
+        assert_eq!(logger.entries_count(), 4);
+    }
+
+    #[test]
+    fn timelogger_without_weekday() {
+        let full = "2017/12/18 Mon | FLEX RESET\n\
+                    2017/12/18 Mon | Work 08:00:00 12:00:00\n\
+                    2017/12/19 Tue | Vacation UNDEF UNDEF\n";
+        let compact = "2017/12/18 | FLEX RESET\n\
+                       2017/12/18 | Work 08:00:00 12:00:00\n\
+                       2017/12/19 | Vacation UNDEF UNDEF\n";
+
+        let mut logger: TimeLogger = compact.parse().unwrap();
+        assert_eq!(logger.write_entries(), full);
+        logger.set_write_weekday(false);
+        assert_eq!(logger.write_entries(), compact);
+
+        let mut logger: TimeLogger = full.parse().unwrap();
+        logger.set_write_weekday(false);
+        assert_eq!(logger.write_entries(), compact);
+
+        assert!("2017/12/18 Tue | Work 08:00:00 12:00:00"
+            .parse::<TimeLogger>()
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_interleaved_dates_coalesce() {
+        let mon_1 = "2017/12/18 Mon | Work 06:31:00 07:00:00";
+        let tue_1 = "2017/12/19 Tue | Work 07:31:00 11:50:00";
+        let mon_2 = "2017/12/18 Mon | Work 07:31:00 12:00:00";
+
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger
+            .read_entries(format!("{}\n{}\n{}\n", mon_1, tue_1, mon_2).as_str())
+            .unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let mon_tld = format!("{}\n{}", mon_1, mon_2)
+            .parse::<TimeLogDay>()
+            .unwrap();
+        assert_eq!(logger.date2logday.len(), 2);
+        assert_eq!(logger.date2logday[&mon], mon_tld);
+        assert_eq!(
+            logger.write_entries(),
+            format!("{}\n{}\n{}\n", mon_1, mon_2, tue_1)
+        );
+    }
+
+    #[test]
+    fn timelogger_migrate_to_utc() {
+        let s = "2017/12/18 Mon | Work 00:30:00 UNDEF\n\
+                 2017/12/18 Mon | Work 09:00:00 17:00:00\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        logger.migrate_to_utc(FixedOffset::east(3600)).unwrap();
+        assert!(logger.is_utc());
+        let migrated = "# timelog: utc\n\
+                        2017/12/17 Sun | Work 23:30:00 UNDEF\n\
+                        2017/12/18 Mon | Work 08:00:00 16:00:00\n\
+                        2017/12/19 Tue | Vacation UNDEF UNDEF\n";
+        assert_eq!(logger.write_entries(), migrated);
+        assert!(logger.migrate_to_utc(FixedOffset::east(3600)).is_err());
+
+        let reread: TimeLogger = migrated.parse().unwrap();
+        assert!(reread.is_utc());
+        assert_eq!(reread.write_entries(), migrated);
+    }
+
+    #[test]
+    fn timelogger_migrate_to_utc_across_midnight() {
+        let s = "2017/12/18 Mon | Work 00:30:00 08:00:00\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        assert!(logger.migrate_to_utc(FixedOffset::east(3600)).is_err());
+        assert!(!logger.is_utc());
+        assert_eq!(logger.write_entries(), s);
+    }
+
+    #[test]
+    fn timelogger_comments_and_blank_lines() {
+        let s = "# Timelog for ACME\n\
+                 # Format: date | type start end\n\
+                 \n\
+                 2017/12/18 Mon | Work 06:31:00 07:00:00\n\
+                 # Dentist in the morning\n\
+                 \n\
+                 2017/12/19 Tue | Work 09:00:00 17:00:00\n";
+
+        let logger: TimeLogger = s.parse().unwrap();
+        assert_eq!(logger.date2logday.len(), 2);
+        assert_eq!(
+            logger.write_entries(),
+            "# Timelog for ACME\n\
+             # Format: date | type start end\n\
+             2017/12/18 Mon | Work 06:31:00 07:00:00\n\
+             2017/12/19 Tue | Work 09:00:00 17:00:00\n"
+        );
+    }
+
+    #[test]
+    fn timelogger_totals_by_project() {
+        let s = "2017/12/18 Mon | Work@acme 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work@initech 13:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work@acme 08:00:00 16:30:00\n\
+                 2017/12/20 Wed | Work 09:00:00 10:00:00\n\
+                 2017/12/21 Thu | Vacation UNDEF UNDEF\n\
+                 2017/12/26 Tue | Work@acme 08:00:00 16:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        assert_eq!(
+            logger.totals_by_project(mon, sun),
+            vec![
+                (None, Duration::hours(1)),
+                (Some(String::from("acme")), Duration::minutes(12 * 60 + 30)),
+                (Some(String::from("initech")), Duration::hours(4)),
+            ]
+        );
+        assert_eq!(
+            logger.totals_by_type(mon, sun),
+            vec![
+                (TimeLogEntryType::Work, Duration::minutes(17 * 60 + 30)),
+                (TimeLogEntryType::Vacation, Duration::hours(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn timelogger_totals_by_type_order() {
+        let s = "2017/12/18 Mon | ParentalLeave UNDEF UNDEF\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n\
+                 2017/12/20 Wed | Sickness UNDEF UNDEF\n\
+                 2017/12/21 Thu | Holiday UNDEF UNDEF\n\
+                 2017/12/22 Fri | Work 08:00:00 12:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        let types: Vec<TimeLogEntryType> = logger
+            .totals_by_type(mon, sun)
+            .into_iter()
+            .map(|(ty, _)| ty)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TimeLogEntryType::Work,
+                TimeLogEntryType::Holiday,
+                TimeLogEntryType::Sickness,
+                TimeLogEntryType::Vacation,
+                TimeLogEntryType::ParentalLeave,
+            ]
+        );
+    }
+
+    #[test]
+    fn timelogger_totals_within() {
+        let s = "2017/12/18 Mon | Work@acme 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work 13:00:00 19:00:00\n\
+                 2017/12/19 Tue | Vacation UNDEF UNDEF\n\
+                 2017/12/20 Wed | Work@acme 15:00:00 18:30:00\n\
+                 2017/12/21 Thu | Work 08:00:00 15:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        let after_five = (
+            NaiveTime::from_hms(17, 0, 0),
+            NaiveTime::from_hms(23, 59, 59),
+        );
+
+        assert_eq!(
+            logger.totals_within(mon, sun, after_five, |e| Some(e.ty())),
+            vec![
+                (TimeLogEntryType::Work, Duration::minutes(3 * 60 + 30)),
+                (TimeLogEntryType::Vacation, Duration::hours(8)),
+            ]
+        );
+        assert_eq!(
+            logger.totals_within(mon, sun, after_five, |e| e.project().map(String::from)),
+            vec![(String::from("acme"), Duration::minutes(90))]
+        );
+
+        // The whole day agrees with the week, auto lunch included
+        let mut logger = logger;
+        logger.set_auto_lunch(Some(AutoLunch {
+            deduct: Duration::minutes(30),
+            above: Duration::hours(6),
+        }));
+        let whole_day = (
+            NaiveTime::from_hms(0, 0, 0),
+            NaiveTime::from_hms_nano(23, 59, 59, 999_999_999),
+        );
+        let work = logger.totals_within(mon, sun, whole_day, |e| {
+            (e.ty() == TimeLogEntryType::Work).then_some(())
+        });
+        assert_eq!(
+            work,
+            vec![(
+                (),
+                logger.compute_logged_time_between(mon, sun, TimeLogEntryType::Work)
+            )]
+        );
+        assert_eq!(work, vec![((), Duration::hours(20))]);
+    }
+
+    #[test]
+    fn timelogger_rename_project() {
+        let s = "2017/12/18 Mon | Work@acme 08:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work@initech 13:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work@acme 08:00:00 16:30:00\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        assert_eq!(logger.rename_project("acme", "acme-corp"), 2);
+        assert_eq!(logger.rename_project("acme", "acme-corp"), 0);
+        assert_eq!(
+            logger.totals_by_project(mon, sun),
+            vec![
+                (
+                    Some(String::from("acme-corp")),
+                    Duration::minutes(12 * 60 + 30)
+                ),
+                (Some(String::from("initech")), Duration::hours(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn timelogger_log_truncates_to_minutes() {
+        let mut logger = TimeLogger::new(PathBuf::new());
+        let date = NaiveDate::from_ymd(2017, 12, 18);
+        let start = logger.log_start(date, NaiveTime::from_hms_milli(8, 0, 45, 500));
+        let end = logger.log_end(date, NaiveTime::from_hms(12, 30, 59));
+        assert_eq!(start.start(), Some(NaiveTime::from_hms(8, 0, 0)));
+        assert_eq!(end.end(), Some(NaiveTime::from_hms(12, 30, 0)));
+        assert_eq!(
+            logger.compute_logged_time_between(date, date, TimeLogEntryType::Work),
+            Duration::minutes(4 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn timelogger_log_keeps_seconds() {
+        let mut logger = TimeLogger::new(PathBuf::new());
+        logger.set_time_precision(TimePrecision::Seconds);
+        let date = NaiveDate::from_ymd(2017, 12, 18);
+        let start = logger.log_start(date, NaiveTime::from_hms_milli(8, 0, 45, 500));
+        let end = logger.log_end(date, NaiveTime::from_hms(12, 30, 59));
+        assert_eq!(start.start(), Some(NaiveTime::from_hms(8, 0, 45)));
+        assert_eq!(end.end(), Some(NaiveTime::from_hms(12, 30, 59)));
+        assert_eq!(
+            logger.compute_logged_time_between(date, date, TimeLogEntryType::Work),
+            Duration::seconds(4 * 3600 + 30 * 60 + 14)
+        );
+    }
+
+    #[test]
+    fn timelogger_grouped_totals() {
+        let s = "2017/11/30 Thu | Work 08:00:00 16:00:00\n\
+                 2017/12/01 Fri | Work 08:00:00 12:00:00\n\
+                 2017/12/04 Mon | Vacation UNDEF UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        let from = NaiveDate::from_ymd(2017, 11, 15);
+        let to = NaiveDate::from_ymd(2017, 12, 15);
+
+        assert_eq!(
+            logger.grouped_totals(from, to, Grouping::Month),
+            vec![
+                (from, NaiveDate::from_ymd(2017, 11, 30), Duration::hours(8)),
+                (NaiveDate::from_ymd(2017, 12, 1), to, Duration::hours(12)),
+            ]
+        );
+        assert_eq!(logger.grouped_totals(from, to, Grouping::Week).len(), 5);
+        assert_eq!("year".parse::<Grouping>(), Err(TimeLogError::inv_inp("")));
+    }
+
+    #[test]
+    fn timelogger_week_summary() {
+        let s = "2017/12/11 Mon | Work 08:00:00 18:00:00\n\
+                 2017/12/18 Mon | Work 08:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 12:00:00\n\
+                 2017/12/19 Tue | Work 13:00:00 UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        assert_eq!(
+            logger.week_summary_with(tue, Some(NaiveTime::from_hms(15, 0, 0))),
+            Ok(PeriodSummary {
+                logged: Duration::hours(15),
+                loggable: Duration::hours(40),
+                flex: Duration::hours(30),
+                remaining: Duration::hours(55),
+            })
+        );
+        assert_eq!(
+            logger.week_summary_with(tue, None).map(|s| s.logged),
+            Ok(Duration::hours(13))
+        );
+    }
+
+    #[test]
+    fn timelogger_week_summary_without_flex() {
+        let s = "2017/12/11 Mon | Work 08:00:00 18:00:00\n\
+                 2017/12/18 Mon | Work 08:00:00 17:00:00\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let with_flex = logger.week_summary_with(mon, None).unwrap();
+        assert_eq!(with_flex.remaining, Duration::hours(40 - 9 + 30));
+
+        logger.set_flex_enabled(false);
+        let without_flex = logger.week_summary_with(mon, None).unwrap();
+        assert_eq!(without_flex.flex, Duration::zero());
+        assert_eq!(without_flex.remaining, Duration::hours(40 - 9));
+    }
+
+    #[test]
+    fn timelogger_today_summary() {
+        let s = "2017/12/19 Tue | Work 07:30:00 11:30:00\n\
+                 2017/12/19 Tue | Work 12:00:00 UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        assert_eq!(
+            logger.today_summary(tue, Some(NaiveTime::from_hms(14, 0, 0))),
+            Ok(DaySummary {
+                worked: Duration::hours(6),
+                loggable: Duration::hours(8),
+                remaining: Duration::hours(2),
+                open_since: Some(NaiveTime::from_hms(12, 0, 0)),
+            })
+        );
+        assert!(logger
+            .today_summary(NaiveDate::from_ymd(2017, 12, 20), None)
+            .is_err());
+    }
+
+    #[test]
+    fn timelogger_fractional_daily_loggable() {
+        let s = "2017/12/19 Tue | Work 08:00:00 16:00:00\n\
+                 2017/12/20 Wed | Vacation UNDEF UNDEF\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+        logger.set_daily_loggable(Duration::minutes(7 * 60 + 30));
+
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        let week = logger.compute_loggable_time_in_week_of(tue, TimeLogEntryType::Work);
+        assert_eq!(week, Duration::minutes(37 * 60 + 30));
+        assert_eq!(
+            logger.today_summary(tue, None).unwrap().remaining,
+            Duration::minutes(-30)
+        );
+        // A day of leave without times is as long as the configured day
+        assert_eq!(
+            logger.today_summary(tue.succ(), None).unwrap().remaining,
+            Duration::zero()
+        );
+        assert_eq!(
+            logger.time_logged_in_week_of_with(tue, None).unwrap(),
+            Duration::minutes(15 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn timelogger_logged_time_today() {
+        let s = "2017/12/19 Tue | Work 07:30:00 11:30:00\n\
+                 2017/12/19 Tue | Work 12:00:00 UNDEF\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let clock = FixedClock(NaiveDate::from_ymd(2017, 12, 19).and_hms(13, 15, 0));
+        assert_eq!(
+            logger.logged_time_today(&clock),
+            Ok(Duration::minutes(5 * 60 + 15))
+        );
+        let clock = FixedClock(NaiveDate::from_ymd(2017, 12, 20).and_hms(13, 15, 0));
+        assert!(logger.logged_time_today(&clock).is_err());
+
+        // Before the day boundary it is still the day before, with the entry open past midnight
+        let s = "2017/12/19 Tue | Work 12:00:00 16:00:00\n\
+                 2017/12/19 Tue | Work 22:00:00 UNDEF\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+        logger.set_day_boundary(Some(NaiveTime::from_hms(3, 0, 0)));
+        let clock = FixedClock(NaiveDate::from_ymd(2017, 12, 20).and_hms(1, 30, 0));
+        assert_eq!(
+            logger.logged_time_today(&clock),
+            Ok(Duration::minutes(7 * 60 + 30))
+        );
+        let status = logger.status(&clock).unwrap();
+        assert_eq!(status.worked, Duration::minutes(7 * 60 + 30));
+        assert_eq!(status.remaining, Duration::minutes(30));
+        assert_eq!(status.open_since, Some(NaiveTime::from_hms(22, 0, 0)));
+    }
+
+    #[test]
+    fn timelogger_overtime_keeps_remaining() {
+        let s = "2017/12/19 Tue | Work 08:00:00 16:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+        let tue = NaiveDate::from_ymd(2017, 12, 19);
+        let day = logger.today_summary(tue, None).unwrap();
+        let week = logger.week_summary_with(tue, None).unwrap();
+
+        let s = format!("{}2017/12/19 Tue | Overtime 16:00:00 18:00:00\n", s);
+        let logger: TimeLogger = s.parse().unwrap();
+        let day_overtime = logger.today_summary(tue, None).unwrap();
+        assert_eq!(day_overtime.worked, day.worked + Duration::hours(2));
+        assert_eq!(day_overtime.remaining, day.remaining);
+
+        let week_overtime = logger.week_summary_with(tue, None).unwrap();
+        assert_eq!(week_overtime.logged, week.logged + Duration::hours(2));
+        assert_eq!(week_overtime.remaining, week.remaining);
+    }
+
+    #[test]
+    fn timelogger_recent_entries() {
+        let s = "2017/12/18 Mon | Work 13:00:00 17:00:00\n\
+                 2017/12/19 Tue | Work 08:00:00 12:00:00\n\
+                 2017/12/19 Tue | Work 12:30:00 UNDEF\n\
+                 2017/12/18 Mon | Work 08:00:00 12:00:00\n";
+        let logger: TimeLogger = s.parse().unwrap();
+
+        let recent: Vec<String> = logger
+            .recent_entries(3)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            recent,
+            vec![
+                "2017/12/19 Tue | Work 12:30:00 UNDEF",
+                "2017/12/19 Tue | Work 08:00:00 12:00:00",
+                "2017/12/18 Mon | Work 13:00:00 17:00:00",
+            ]
+        );
+        assert_eq!(logger.recent_entries(10).len(), 4);
+    }
+
+    #[test]
+    fn timelogger_compact() {
+        let s = "2017/12/18 Mon | Work 08:00:00 10:00:00\n\
+                 2017/12/18 Mon | Work 10:00:00 12:00:00\n\
+                 2017/12/18 Mon | Work 12:00:00 13:00:00\n\
+                 2017/12/18 Mon | Work 13:30:00 16:00:00\n\
+                 2017/12/19 Tue | Work@acme 08:00:00 12:00:00\n\
+                 2017/12/19 Tue | Work@initech 12:00:00 14:00:00\n\
+                 2017/12/20 Wed | Vacation 08:00:00 12:00:00\n\
+                 2017/12/20 Wed | Work 12:00:00 16:00:00\n";
+        let mut logger: TimeLogger = s.parse().unwrap();
+
+        let mon = NaiveDate::from_ymd(2017, 12, 18);
+        let sun = NaiveDate::from_ymd(2017, 12, 24);
+        let totals = |l: &TimeLogger| {
+            TimeLogEntryType::iterator()
+                .map(|ty| l.compute_logged_time_between(mon, sun, *ty))
+                .collect::<Vec<_>>()
+        };
+        let before = totals(&logger);
+
+        assert_eq!(logger.compact(), 2);
+        assert_eq!(logger.compact(), 0);
+        assert_eq!(totals(&logger), before);
+        assert_eq!(logger.day(mon).unwrap().entries().len(), 2);
+    }
+
     #[test]
     fn timelogger_consistent_serialization() {
         let nov_mon_1 = "2017/11/13 Mon | Work 08:00:00 18:00:00";
@@ -847,11 +3384,7 @@ mod tests {
             nov_mon_1, nov_tue_1, nov_wed_1, mon_1, mon_2, tue_1, tue_2, wed_1, wed_2
         );
 
-        let mut logger = TimeLogger {
-            file_path: PathBuf::new(),
-            date2logday: HashMap::new(),
-        };
-        logger.read_entries(s.as_str()).unwrap();
+        let logger: TimeLogger = s.parse().unwrap();
         assert_eq!(logger.write_entries(), s);
     }
 }